@@ -1,8 +1,34 @@
 use uuid::Uuid;
 
+use crate::agent::quota;
 use crate::ai::llm::{ChatMessage, LlmClient};
+use crate::ai::tokenizer::count_tokens;
+use crate::db::models::Message;
 use crate::db::Database;
-use std::path::{Path, PathBuf};
+
+/// Tokens the conversation occupies in the model's context window.
+///
+/// Anchored on the last API-reported usage (prompt + completion of the most
+/// recent assistant call), plus the counted size of anything saved after it.
+/// Falls back to summing per-message counts when there is no usage yet or the
+/// history was summarized since.
+pub fn context_tokens(messages: &[Message]) -> i64 {
+    let counted = |msgs: &[Message]| msgs.iter().map(|m| m.token_count as i64).sum::<i64>();
+
+    let anchor = messages
+        .iter()
+        .rposition(|m| m.prompt_tokens.is_some() || m.completion_tokens.is_some());
+
+    match anchor {
+        Some(i) if !messages[i + 1..].iter().any(|m| m.role == "system") => {
+            let m = &messages[i];
+            m.prompt_tokens.unwrap_or(0) as i64
+                + m.completion_tokens.unwrap_or(0) as i64
+                + counted(&messages[i + 1..])
+        }
+        _ => counted(messages),
+    }
+}
 
 /// Manages conversation context: auto-pruning, summarization, and user profiling.
 pub struct ContextManager {
@@ -20,9 +46,11 @@ impl ContextManager {
         &self,
         db: &Database,
         llm: &LlmClient,
+        user_id: i64,
         conversation_id: Uuid,
     ) -> anyhow::Result<bool> {
-        let total_tokens = db.get_total_tokens(conversation_id).await?;
+        let messages: Vec<Message> = db.get_messages(conversation_id).await?;
+        let total_tokens = context_tokens(&messages);
 
         if (total_tokens as usize) < self.max_tokens {
             return Ok(false);
@@ -35,8 +63,6 @@ impl ContextManager {
             conversation_id
         );
 
-        // Get all messages
-        let messages: Vec<crate::db::models::Message> = db.get_messages(conversation_id).await?;
         if messages.len() <= 4 {
             // Too few messages to prune meaningfully
             return Ok(false);
@@ -58,15 +84,18 @@ impl ContextManager {
                 content: "Summarize the following conversation into a concise paragraph. \
                           Preserve key facts, decisions, and any important user information."
                     .to_string(),
+                ..Default::default()
             },
             ChatMessage {
                 role: "user".to_string(),
                 content: summary_text,
+                ..Default::default()
             },
         ];
 
         let response = llm.chat(&summary_prompt).await?;
-        let summary = response.text;
+        quota::record(db, user_id, Some(conversation_id), "context_summary", &response).await?;
+        let summary = response.text.clone();
 
         // Delete the oldest messages
         let keep_count = (messages.len() - half) as i64;
@@ -76,14 +105,10 @@ impl ContextManager {
         tracing::info!("Deleted {} old messages from conv {}", deleted, conversation_id);
 
         // Insert the summary as a "system" message at the start
-        let token_count = LlmClient::estimate_tokens(&summary);
-        db.save_message(
-            conversation_id,
-            "system",
-            &format!("[Previous conversation summary]: {}", summary),
-            token_count,
-        )
-        .await?;
+        let summary_message = format!("[Previous conversation summary]: {}", summary);
+        let token_count = count_tokens(&response.model, &summary_message);
+        db.save_message(conversation_id, "system", &summary_message, token_count)
+            .await?;
 
         // Also update the conversation's global summary
         db.update_conversation_summary(conversation_id, &summary)
@@ -130,14 +155,17 @@ impl ContextManager {
                         user.profile_summary.clone()
                     }
                 ),
+                ..Default::default()
             },
             ChatMessage {
                 role: "user".to_string(),
                 content: conversation_text,
+                ..Default::default()
             },
         ];
 
         let response = llm.chat(&prompt).await?;
+        quota::record(db, user_id, Some(conversation_id), "profile", &response).await?;
 
         if !response.text.contains("NO_UPDATE") && !response.text.is_empty() {
             db.update_user_profile(user_id, &response.text).await?;
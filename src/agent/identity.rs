@@ -1,4 +1,4 @@
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use tokio::fs;
 
 /// Manages the bot's persona files (SOUL.md, IDENTITY.md, SECURITY.md).
@@ -48,6 +48,7 @@ impl IdentityManager {
         &self,
         user_profile: &str,
         available_tools_desc: &str,
+        user_language: Option<&str>,
     ) -> anyhow::Result<String> {
         let soul = self.load_file("SOUL").await.unwrap_or_default();
         let identity = self.load_file("IDENTITY").await.unwrap_or_default();
@@ -90,9 +91,18 @@ impl IdentityManager {
             "- If the user sends a voice message, it has been transcribed for you. \
              Respond naturally.\n\
              - Keep responses concise for voice output (they will be spoken aloud via TTS).\n\
-             - You can use tools by responding with a JSON tool call.\n",
+             - When an action is needed, call the provided tools directly instead of \
+             describing the call in text.\n",
         );
 
+        if let Some(language) = user_language {
+            prompt.push_str(&format!(
+                "- The user is speaking language code '{}'. Reply in that language \
+                 unless they ask otherwise.\n",
+                language
+            ));
+        }
+
         Ok(prompt)
     }
 
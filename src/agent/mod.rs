@@ -2,4 +2,5 @@ pub mod approval;
 pub mod context;
 pub mod executor;
 pub mod identity;
+pub mod quota;
 pub mod tools;
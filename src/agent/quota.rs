@@ -0,0 +1,114 @@
+use uuid::Uuid;
+
+use crate::ai::llm::LlmResponse;
+use crate::config::AppConfig;
+use crate::db::Database;
+
+/// Token allowance for one user. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Quota {
+    pub daily: Option<i64>,
+    pub monthly: Option<i64>,
+}
+
+impl Quota {
+    /// Role quota from config, overridden by `quota_daily` / `quota_monthly`
+    /// in the user's settings (set by an admin via /quota).
+    pub fn for_user(config: &AppConfig, user_id: i64, settings: &serde_json::Value) -> Self {
+        let (daily, monthly) = if config.is_admin(user_id) {
+            (config.quota_admin_daily, config.quota_admin_monthly)
+        } else {
+            (config.quota_user_daily, config.quota_user_monthly)
+        };
+
+        let daily = settings.get("quota_daily").and_then(|v| v.as_i64()).unwrap_or(daily);
+        let monthly = settings.get("quota_monthly").and_then(|v| v.as_i64()).unwrap_or(monthly);
+
+        Self {
+            daily: (daily > 0).then_some(daily),
+            monthly: (monthly > 0).then_some(monthly),
+        }
+    }
+}
+
+/// A user's consumption measured against their quota.
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaStatus {
+    pub quota: Quota,
+    pub used_today: i64,
+    pub used_month: i64,
+}
+
+impl QuotaStatus {
+    pub async fn load(
+        db: &Database,
+        config: &AppConfig,
+        user_id: i64,
+        settings: &serde_json::Value,
+    ) -> anyhow::Result<Self> {
+        let (used_today, used_month) = db.get_user_usage(user_id).await?;
+        Ok(Self {
+            quota: Quota::for_user(config, user_id, settings),
+            used_today,
+            used_month,
+        })
+    }
+
+    /// User-facing explanation if either quota is used up.
+    pub fn exceeded_message(&self) -> Option<String> {
+        if let Some(limit) = self.quota.daily.filter(|&l| self.used_today >= l) {
+            return Some(format!(
+                "🚫 You've used your daily token quota ({} / {}). It resets at midnight UTC.",
+                self.used_today, limit
+            ));
+        }
+        if let Some(limit) = self.quota.monthly.filter(|&l| self.used_month >= l) {
+            return Some(format!(
+                "🚫 You've used your monthly token quota ({} / {}). It resets on the 1st (UTC).",
+                self.used_month, limit
+            ));
+        }
+        None
+    }
+
+    /// Two-line summary for /usage.
+    pub fn describe(&self) -> String {
+        format!(
+            "📅 Today: {}\n🗓 This month: {}",
+            usage_against(self.used_today, self.quota.daily),
+            usage_against(self.used_month, self.quota.monthly)
+        )
+    }
+}
+
+fn usage_against(used: i64, limit: Option<i64>) -> String {
+    match limit {
+        Some(limit) => format!("{} / {} tokens", used, limit),
+        None => format!("{} tokens (no limit)", used),
+    }
+}
+
+/// Write an LLM call's reported usage to the ledger.
+/// `kind` names what the call was for: "chat", "context_summary", "profile", ...
+pub async fn record(
+    db: &Database,
+    user_id: i64,
+    conversation_id: Option<Uuid>,
+    kind: &str,
+    response: &LlmResponse,
+) -> anyhow::Result<()> {
+    let Some(usage) = &response.usage else {
+        tracing::debug!("No usage reported by '{}' for {} call", response.model, kind);
+        return Ok(());
+    };
+
+    db.record_usage(
+        user_id,
+        conversation_id,
+        kind,
+        &response.model,
+        usage.prompt_tokens as i32,
+        usage.completion_tokens as i32,
+    )
+    .await
+}
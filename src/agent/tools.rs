@@ -8,9 +8,11 @@ pub struct ToolDefinition {
     pub parameters: serde_json::Value,
 }
 
-/// A parsed tool call from the LLM's response.
+/// A structured tool call returned by the LLM.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
+    /// Provider-assigned call ID, used to match tool results to calls.
+    pub id: String,
     pub name: String,
     pub arguments: serde_json::Value,
 }
@@ -78,48 +80,26 @@ impl ToolRegistry {
         Self { tools }
     }
 
-    /// Generate a human-readable description of all tools for the system prompt.
+    /// Tool definitions to send to the LLM as native function specs.
+    pub fn definitions(&self) -> &[ToolDefinition] {
+        &self.tools
+    }
+
+    /// Generate a short human-readable list of tools for the system prompt.
+    /// The full schemas are sent separately via the API's `tools` field.
     pub fn describe_for_prompt(&self) -> String {
-        let mut desc = String::from(
-            "You have access to the following tools. To use a tool, respond with ONLY a JSON \
-             object in the format: {\"tool\": \"tool_name\", \"args\": {...}}\n\n",
-        );
+        let mut desc = String::new();
 
         for tool in &self.tools {
-            desc.push_str(&format!(
-                "- **{}**: {}\n  Parameters: {}\n\n",
-                tool.name,
-                tool.description,
-                serde_json::to_string_pretty(&tool.parameters).unwrap_or_default()
-            ));
+            desc.push_str(&format!("- **{}**: {}\n", tool.name, tool.description));
         }
 
         desc
     }
+}
 
-    /// Try to parse a tool call from the LLM's text response.
-    pub fn parse_tool_call(text: &str) -> Option<ToolCall> {
-        // Try to find a JSON object in the response
-        let trimmed = text.trim();
-
-        // Look for JSON that starts with { and contains "tool"
-        if let Some(start) = trimmed.find('{') {
-            if let Some(end) = trimmed.rfind('}') {
-                let json_str = &trimmed[start..=end];
-                if let Ok(val) = serde_json::from_str::<serde_json::Value>(json_str) {
-                    if let (Some(tool), Some(args)) = (
-                        val.get("tool").and_then(|t| t.as_str()),
-                        val.get("args"),
-                    ) {
-                        return Some(ToolCall {
-                            name: tool.to_string(),
-                            arguments: args.clone(),
-                        });
-                    }
-                }
-            }
-        }
-
-        None
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        Self::new()
     }
 }
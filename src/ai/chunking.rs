@@ -0,0 +1,86 @@
+use std::ops::Range;
+
+use crate::ai::stt::Segment;
+
+/// One whisper call's worth of audio.
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub range: Range<usize>,
+    /// Samples at the start shared with the previous window (0 after a clean cut).
+    pub overlap: usize,
+}
+
+/// Cover `regions` with windows of at most `max_len` samples. Regions that fit
+/// become one window; longer ones are split into windows overlapping by
+/// `overlap` samples so words on a boundary are heard whole at least once.
+pub fn plan_windows(regions: &[Range<usize>], max_len: usize, overlap: usize) -> Vec<Window> {
+    let overlap = overlap.min(max_len / 2);
+    let mut windows = Vec::new();
+
+    for region in regions {
+        let mut start = region.start;
+        let mut shared = 0;
+        loop {
+            let end = (start + max_len).min(region.end);
+            windows.push(Window {
+                range: start..end,
+                overlap: shared,
+            });
+            if end == region.end {
+                break;
+            }
+            start = end - overlap;
+            shared = overlap;
+        }
+    }
+
+    windows
+}
+
+/// Append a window's segments (already in absolute time) to the transcript so far.
+/// When the window overlapped the previous one, each side keeps the segments that
+/// start on its half of the overlap, then words repeated across the seam are dropped.
+pub fn stitch(acc: &mut Vec<Segment>, mut next: Vec<Segment>, overlap_ms: Range<i64>) {
+    if overlap_ms.is_empty() || acc.is_empty() {
+        acc.extend(next);
+        return;
+    }
+
+    let seam = overlap_ms.start + (overlap_ms.end - overlap_ms.start) / 2;
+    acc.retain(|s| s.start_ms < seam);
+    next.retain(|s| s.start_ms >= seam);
+
+    if let (Some(last), Some(first)) = (acc.last(), next.first_mut()) {
+        let repeated = repeated_words(&last.text, &first.text);
+        if repeated > 0 {
+            first.text = first
+                .text
+                .split_whitespace()
+                .skip(repeated)
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+    }
+
+    acc.extend(next.into_iter().filter(|s| !s.text.is_empty()));
+}
+
+/// Longest run of words that ends `before` and starts `after` (case and
+/// punctuation insensitive), capped to keep short coincidences from matching.
+fn repeated_words(before: &str, after: &str) -> usize {
+    const MAX_WORDS: usize = 12;
+
+    let normalize = |w: &str| -> String {
+        w.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let tail: Vec<String> = before.split_whitespace().map(normalize).collect();
+    let head: Vec<String> = after.split_whitespace().map(normalize).collect();
+
+    (1..=MAX_WORDS.min(tail.len()).min(head.len()))
+        .rev()
+        .find(|&k| tail[tail.len() - k..] == head[..k])
+        .unwrap_or(0)
+}
@@ -1,29 +1,13 @@
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::config::AppConfig;
-
-#[derive(Debug, Clone, Serialize)]
-struct GroqMessage {
-    role: String,
-    content: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct GroqResponse {
-    choices: Vec<GroqChoice>,
-    usage: Option<GroqUsage>,
-}
-
-#[derive(Debug, Deserialize)]
-struct GroqChoice {
-    message: GroqMessageContent,
-}
+use serde::Deserialize;
+use tokio::sync::mpsc;
 
-#[derive(Debug, Deserialize)]
-struct GroqMessageContent {
-    content: String,
-}
+use crate::agent::tools::{ToolCall, ToolDefinition};
+use crate::ai::provider::{ApiError, LlmProvider, OpenAiCompatibleProvider};
+use crate::config::AppConfig;
 
 #[derive(Debug, Deserialize)]
 pub struct GroqUsage {
@@ -32,94 +16,185 @@ pub struct GroqUsage {
     pub total_tokens: u32,
 }
 
+/// Chat client used throughout the bot. Delegates to the configured `LlmProvider`.
+#[derive(Clone)]
 pub struct LlmClient {
-    client: Client,
-    api_key: String,
+    provider: Arc<dyn LlmProvider>,
     model: String,
+    /// Models to try, in order, when the requested one keeps failing.
+    fallback_models: Vec<String>,
+    /// Retries per model for rate limits, server errors and timeouts.
+    max_retries: u32,
+    /// First backoff delay; doubles on each retry.
+    retry_base_delay: Duration,
+    /// Longest we'll wait before a retry; longer server hints skip to the next model.
+    max_retry_wait: Duration,
 }
 
-/// A simple (role, content) pair for building the messages array.
+/// A single entry of the messages array.
+/// Assistant turns may carry `tool_calls`; `tool` turns reference one via `tool_call_id`.
+/// User turns may attach `images` (data URLs) for vision-capable models.
+#[derive(Debug, Clone, Default)]
 pub struct ChatMessage {
     pub role: String,
     pub content: String,
+    pub tool_calls: Vec<ToolCall>,
+    pub tool_call_id: Option<String>,
+    pub images: Vec<String>,
 }
 
 pub struct LlmResponse {
     pub text: String,
+    /// Structured tool calls requested by the model (may be several per turn).
+    pub tool_calls: Vec<ToolCall>,
     pub usage: Option<GroqUsage>,
+    /// The model that actually produced this response (may be a fallback).
+    pub model: String,
+}
+
+/// An incremental event from a streaming completion.
+pub enum StreamEvent {
+    /// A chunk of assistant text.
+    Delta(String),
+    /// The stream has finished; carries the fully assembled response.
+    Done(LlmResponse),
 }
 
+/// Receiver side of a streaming completion.
+pub type LlmStream = mpsc::Receiver<anyhow::Result<StreamEvent>>;
+
 impl LlmClient {
     pub fn new(config: &AppConfig) -> Self {
+        Self::with_provider(Arc::new(OpenAiCompatibleProvider::from_config(config)), config)
+    }
+
+    /// Create a client backed by a specific provider.
+    pub fn with_provider(provider: Arc<dyn LlmProvider>, config: &AppConfig) -> Self {
         Self {
-            client: Client::builder()
-                .timeout(std::time::Duration::from_secs(60))
-                .build()
-                .unwrap_or_default(),
-            api_key: config.groq_api_key.clone(),
+            provider,
             model: config.groq_model.clone(),
+            fallback_models: config.llm_fallback_models.clone(),
+            max_retries: config.llm_max_retries,
+            retry_base_delay: Duration::from_millis(config.llm_retry_base_ms),
+            max_retry_wait: Duration::from_secs(config.llm_retry_max_wait_secs),
         }
     }
 
+    /// Name of the active provider (e.g. "groq").
+    pub fn provider_name(&self) -> &str {
+        self.provider.name()
+    }
+
     /// Get the default model name.
     pub fn default_model(&self) -> &str {
         &self.model
     }
 
-    /// Send a conversation to Groq and get the assistant's reply.
+    /// Send a conversation to the provider and get the assistant's reply.
     pub async fn chat(&self, messages: &[ChatMessage]) -> anyhow::Result<LlmResponse> {
-        self.chat_with_model(messages, &self.model).await
+        self.chat_with_model(messages, &self.model, &[]).await
     }
 
-    /// Send a conversation to Groq using a specific model.
-    pub async fn chat_with_model(&self, messages: &[ChatMessage], model: &str) -> anyhow::Result<LlmResponse> {
-        let groq_messages: Vec<GroqMessage> = messages
-            .iter()
-            .map(|m| GroqMessage {
-                role: m.role.clone(),
-                content: m.content.clone(),
-            })
-            .collect();
-
-        let body = serde_json::json!({
-            "model": model,
-            "messages": groq_messages,
-            "temperature": 0.7,
-            "max_tokens": 2048,
-        });
-
-        let resp = self
-            .client
-            .post("https://api.groq.com/openai/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await?;
-
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let err_body = resp.text().await.unwrap_or_default();
-            anyhow::bail!("Groq API error ({}): {}", status, err_body);
-        }
-
-        let groq_resp: GroqResponse = resp.json().await?;
-
-        let text = groq_resp
-            .choices
-            .first()
-            .map(|c| c.message.content.clone())
-            .unwrap_or_default();
+    /// Send a conversation using a specific model.
+    /// `tools` are offered to the model as native function definitions.
+    /// Retries transient failures and falls back to the configured fallback models;
+    /// `LlmResponse::model` records which model answered.
+    pub async fn chat_with_model(
+        &self,
+        messages: &[ChatMessage],
+        model: &str,
+        tools: &[ToolDefinition],
+    ) -> anyhow::Result<LlmResponse> {
+        self.with_fallback(model, |m| async move { self.provider.chat(messages, &m, tools).await })
+            .await
+    }
 
-        Ok(LlmResponse {
-            text,
-            usage: groq_resp.usage,
+    /// Streaming variant of `chat_with_model`.
+    /// Text arrives as `StreamEvent::Delta`s; the assembled response (including any
+    /// tool calls and usage) is delivered as a final `StreamEvent::Done`.
+    pub async fn chat_stream_with_model(
+        &self,
+        messages: &[ChatMessage],
+        model: &str,
+        tools: &[ToolDefinition],
+    ) -> anyhow::Result<LlmStream> {
+        // Only opening the stream is retried; errors mid-stream surface to the caller
+        self.with_fallback(model, |m| async move {
+            self.provider.chat_stream(messages, &m, tools).await
         })
+        .await
     }
 
-    /// Estimate token count for a string (rough: ~4 chars per token).
-    pub fn estimate_tokens(text: &str) -> i32 {
-        (text.len() as f64 / 4.0).ceil() as i32
+    /// Run `call` against `model`, then each fallback model, retrying transient
+    /// errors with exponential backoff (or the server's requested delay).
+    async fn with_fallback<T, F, Fut>(&self, model: &str, mut call: F) -> anyhow::Result<T>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let mut chain = vec![model.to_string()];
+        for fallback in &self.fallback_models {
+            if !chain.contains(fallback) {
+                chain.push(fallback.clone());
+            }
+        }
+
+        let mut last_err = None;
+
+        'models: for (i, candidate) in chain.iter().enumerate() {
+            if i > 0 {
+                tracing::warn!("Falling back to model '{}'", candidate);
+            }
+
+            for attempt in 0..=self.max_retries {
+                let err = match call(candidate.clone()).await {
+                    Ok(value) => return Ok(value),
+                    Err(e) => e,
+                };
+
+                let api_err = err.downcast_ref::<ApiError>();
+                if api_err.is_some_and(|e| e.is_auth_error()) {
+                    return Err(err);
+                }
+
+                let retryable = match api_err {
+                    Some(e) => e.is_retryable(),
+                    None => err
+                        .downcast_ref::<reqwest::Error>()
+                        .is_some_and(|e| e.is_timeout() || e.is_connect()),
+                };
+                if !retryable || attempt == self.max_retries {
+                    tracing::warn!("Model '{}' failed: {}", candidate, err);
+                    last_err = Some(err);
+                    continue 'models;
+                }
+
+                let delay = api_err
+                    .and_then(|e| e.retry_after)
+                    .unwrap_or_else(|| self.retry_base_delay * 2u32.saturating_pow(attempt));
+                if delay > self.max_retry_wait {
+                    tracing::warn!(
+                        "Model '{}' asks to wait {:.1}s (max {}s), skipping to next model",
+                        candidate,
+                        delay.as_secs_f64(),
+                        self.max_retry_wait.as_secs()
+                    );
+                    last_err = Some(err);
+                    continue 'models;
+                }
+
+                tracing::warn!(
+                    "Model '{}' failed (attempt {}/{}), retrying in {:.1}s: {}",
+                    candidate,
+                    attempt + 1,
+                    self.max_retries + 1,
+                    delay.as_secs_f64(),
+                    err
+                );
+                tokio::time::sleep(delay).await;
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| anyhow::anyhow!("No LLM models configured")))
     }
 }
-
@@ -1,3 +1,16 @@
+pub mod chunking;
 pub mod llm;
+pub mod piper_pool;
+pub mod piper_voice;
+pub mod provider;
+pub mod sentences;
 pub mod stt;
+pub mod stt_backend;
+pub mod stt_pool;
+pub mod subtitles;
+pub mod tokenizer;
 pub mod tts;
+pub mod tts_piper;
+pub mod tts_xtts;
+pub mod vad;
+pub mod whisper_models;
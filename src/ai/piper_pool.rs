@@ -0,0 +1,329 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+
+use crate::ai::piper_voice::PiperVoice;
+use crate::audio::TempFile;
+use crate::config::AppConfig;
+
+/// A long-lived `piper --json-input` process with one voice loaded.
+/// Each request is a JSON line naming an output WAV file; piper answers with
+/// the file's path on stdout once it has been written.
+struct PiperProcess {
+    child: Child,
+    /// `--output_file`, which puts piper in file mode; requests override it.
+    _default_output: TempFile,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    /// Last line piper logged to stderr, for error messages.
+    last_log: Arc<Mutex<String>>,
+    /// When the process last served a request; `None` once the health check has
+    /// seen it answer since, or before its first request.
+    last_request: Option<Instant>,
+}
+
+impl PiperProcess {
+    fn spawn(binary_path: &str, lib_path: &str, voice: &PiperVoice) -> anyhow::Result<Self> {
+        let default_output = TempFile::new();
+        let mut child = Command::new(binary_path)
+            .arg("--model")
+            .arg(&voice.model_path)
+            .arg("--output_file")
+            .arg(&default_output.0)
+            .arg("--json-input")
+            .env("LD_LIBRARY_PATH", lib_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow::anyhow!(
+                "Failed to start piper binary at '{}': {}. \
+                 Make sure the binary exists.",
+                binary_path, e
+            ))?;
+
+        let stdin = child.stdin.take().ok_or_else(|| anyhow::anyhow!("piper stdin unavailable"))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow::anyhow!("piper stdout unavailable"))?;
+        let stderr = child.stderr.take().ok_or_else(|| anyhow::anyhow!("piper stderr unavailable"))?;
+
+        // Piper logs every utterance; drain stderr so the pipe never fills up
+        let last_log = Arc::new(Mutex::new(String::new()));
+        let log = last_log.clone();
+        let voice_id = voice.info.id.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                tracing::debug!("piper[{}]: {}", voice_id, line);
+                *log.lock().unwrap() = line;
+            }
+        });
+
+        Ok(Self {
+            child,
+            _default_output: default_output,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+            last_log,
+            last_request: None,
+        })
+    }
+
+    fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// Synthesize `text` into the WAV file at `output`, returning the path piper
+    /// reports having written.
+    async fn speak(&mut self, text: &str, speaker: Option<i64>, output: &Path) -> anyhow::Result<PathBuf> {
+        let mut request = serde_json::json!({
+            "text": text,
+            "output_file": output,
+        });
+        if let Some(speaker) = speaker {
+            request["speaker_id"] = serde_json::json!(speaker);
+        }
+        let mut line = serde_json::to_string(&request)?;
+        line.push('\n');
+
+        self.stdin.write_all(line.as_bytes()).await?;
+        self.stdin.flush().await?;
+
+        match self.stdout.next_line().await? {
+            Some(written) => Ok(PathBuf::from(written.trim())),
+            None => anyhow::bail!("piper exited: {}", self.last_log.lock().unwrap()),
+        }
+    }
+}
+
+/// What the health check asks running workers to say.
+const PROBE_TEXT: &str = "Ok.";
+
+type Slot = tokio::sync::Mutex<Option<PiperProcess>>;
+
+/// Where a voice's processes live. A slot is `None` until first needed and
+/// after a failure; the next request starts a new process in it.
+struct Worker {
+    voice: PiperVoice,
+    slots: Vec<Slot>,
+}
+
+impl Worker {
+    fn start(&self, pool: &PiperPool) -> anyhow::Result<PiperProcess> {
+        tracing::info!("Starting Piper worker for {}", self.voice.info.id);
+        PiperProcess::spawn(&pool.binary_path, &pool.lib_path, &self.voice)
+    }
+
+    /// An idle slot, preferring ones already running, else the first to free up.
+    async fn acquire(&self) -> tokio::sync::MutexGuard<'_, Option<PiperProcess>> {
+        let mut idle = self.slots.iter().filter_map(|slot| slot.try_lock().ok());
+        if let Some(first) = idle.next() {
+            if first.is_some() {
+                return first;
+            }
+            // Start a new process only if no running one is idle
+            return idle.find(|slot| slot.is_some()).unwrap_or(first);
+        }
+        let waiting = self.slots.iter().map(|slot| Box::pin(slot.lock()));
+        let (guard, _, _) = futures_util::future::select_all(waiting).await;
+        guard
+    }
+}
+
+/// Long-lived Piper processes, up to `processes_per_voice` per voice in use, so
+/// the ONNX model is loaded once instead of on every reply. Least recently used
+/// voices are shut down when more than `max_workers` are running.
+pub struct PiperPool {
+    binary_path: String,
+    lib_path: String,
+    /// Longest a single utterance may take before its worker is restarted.
+    timeout: Duration,
+    max_workers: usize,
+    processes_per_voice: usize,
+    workers: Mutex<Vec<(Arc<Worker>, Instant)>>,
+}
+
+impl PiperPool {
+    /// Create the pool and its health-check task. Must be called inside the tokio runtime.
+    pub fn new(config: &AppConfig) -> Arc<Self> {
+        let pool = Arc::new(Self {
+            binary_path: config.piper_binary_path.clone(),
+            lib_path: config.piper_lib_path.clone(),
+            timeout: Duration::from_secs(config.piper_timeout_secs),
+            max_workers: config.piper_max_workers.max(1),
+            processes_per_voice: config.piper_processes_per_voice.max(1),
+            workers: Mutex::new(Vec::new()),
+        });
+
+        let interval = Duration::from_secs(config.piper_health_check_secs.max(1));
+        tokio::spawn(health_check(Arc::downgrade(&pool), interval));
+
+        pool
+    }
+
+    /// The worker for `voice`, registered (and an idle LRU worker retired) if needed.
+    fn worker(&self, voice: &PiperVoice) -> Arc<Worker> {
+        let mut workers = self.workers.lock().unwrap();
+        if let Some((worker, last_used)) = workers
+            .iter_mut()
+            .find(|(w, _)| w.voice.model_path == voice.model_path)
+        {
+            *last_used = Instant::now();
+            return worker.clone();
+        }
+
+        if workers.len() >= self.max_workers {
+            // Retire the least recently used voice; a request still holding
+            // it finishes first, and its process is killed on drop
+            if let Some(oldest) = workers
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(i, _)| i)
+            {
+                let (worker, _) = workers.remove(oldest);
+                tracing::info!("Stopping Piper worker for {} (least recently used)", worker.voice.info.id);
+            }
+        }
+
+        let worker = Arc::new(Worker {
+            voice: voice.clone(),
+            slots: (0..self.processes_per_voice).map(|_| Slot::new(None)).collect(),
+        });
+        workers.push((worker.clone(), Instant::now()));
+        worker
+    }
+
+    /// Start `voice`'s worker ahead of the first request, so its model is loaded.
+    pub async fn warm_up(&self, voice: &PiperVoice) -> anyhow::Result<()> {
+        let worker = self.worker(voice);
+        let mut process = worker.slots[0].lock().await;
+        if process.is_none() {
+            *process = Some(worker.start(self)?);
+        }
+        Ok(())
+    }
+
+    /// Speak `text` with `voice`, returning a WAV file. A crashed worker is
+    /// restarted and the request retried once; one that times out is restarted
+    /// for the next request.
+    pub async fn synthesize(
+        &self,
+        voice: &PiperVoice,
+        speaker: Option<i64>,
+        text: &str,
+    ) -> anyhow::Result<Vec<u8>> {
+        let worker = self.worker(voice);
+        let mut process = worker.acquire().await;
+        let output = TempFile::new();
+        let mut retried = false;
+
+        loop {
+            let was_started = process.is_some();
+            let running = match process.take().and_then(|mut p| p.is_alive().then_some(p)) {
+                Some(running) => process.insert(running),
+                None => {
+                    if was_started {
+                        tracing::warn!("Piper worker for {} died, restarting", voice.info.id);
+                    }
+                    process.insert(worker.start(self)?)
+                }
+            };
+
+            let result = tokio::time::timeout(self.timeout, running.speak(text, speaker, &output.0)).await;
+            running.last_request = Some(Instant::now());
+            match result {
+                Ok(Ok(written)) => {
+                    let wav = tokio::fs::read(&written).await?;
+                    if wav.len() <= 44 {
+                        anyhow::bail!("Piper TTS produced no audio output");
+                    }
+                    return Ok(wav);
+                }
+                Ok(Err(e)) if !retried => {
+                    tracing::warn!("Piper worker for {} failed ({}), retrying", voice.info.id, e);
+                    *process = None;
+                    retried = true;
+                }
+                Ok(Err(e)) => {
+                    *process = None;
+                    return Err(e.context("Piper TTS failed"));
+                }
+                Err(_) => {
+                    // A hung process can't be trusted with the next request
+                    *process = None;
+                    anyhow::bail!(
+                        "Piper TTS timed out after {}s ({})",
+                        self.timeout.as_secs(),
+                        voice.info.id
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Periodically restart workers whose process has exited, so the next reply
+/// doesn't wait for a model to load. A process that has been idle for a whole
+/// interval since its last request is probed once with a short utterance, to
+/// catch one left hung; busy workers are skipped.
+async fn health_check(pool: Weak<PiperPool>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let Some(pool) = pool.upgrade() else {
+            return;
+        };
+
+        let workers: Vec<Arc<Worker>> = pool
+            .workers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(w, _)| w.clone())
+            .collect();
+        for worker in &workers {
+            for slot in &worker.slots {
+                let Ok(mut process) = slot.try_lock() else {
+                    continue;
+                };
+                let Some(running) = process.as_mut() else {
+                    // Not started yet
+                    continue;
+                };
+                let down = match running.child.try_wait() {
+                    Ok(Some(status)) => Some(status.to_string()),
+                    Err(e) => Some(e.to_string()),
+                    Ok(None) if running.last_request.is_some_and(|t| t.elapsed() >= interval) => {
+                        running.last_request = None;
+                        let probe = TempFile::new();
+                        let answer = running.speak(PROBE_TEXT, None, &probe.0);
+                        match tokio::time::timeout(pool.timeout, answer).await {
+                            Ok(Ok(_)) => None,
+                            Ok(Err(e)) => Some(e.to_string()),
+                            Err(_) => Some(format!("no answer to a probe in {}s", pool.timeout.as_secs())),
+                        }
+                    }
+                    // Running, and either busy recently or already checked
+                    Ok(None) => None,
+                };
+
+                if let Some(reason) = down {
+                    let id = &worker.voice.info.id;
+                    tracing::warn!("Piper worker for {} is down ({}), restarting", id, reason);
+                    match worker.start(&pool) {
+                        Ok(started) => *process = Some(started),
+                        Err(e) => {
+                            *process = None;
+                            tracing::error!("Could not restart Piper worker for {}: {}", id, e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::ai::tts::{Speaker, VoiceInfo};
+
+/// Sample rates a Piper voice may be trained at.
+const SAMPLE_RATES: &[u32] = &[16000, 22050, 24000, 44100, 48000];
+
+/// A voice's `<voice>.onnx.json`, as written by Piper's training/export scripts.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PiperVoiceConfig {
+    pub audio: AudioConfig,
+    #[serde(default)]
+    pub language: Option<LanguageConfig>,
+    #[serde(default)]
+    pub espeak: Option<EspeakConfig>,
+    /// "espeak" (phonemes via espeak-ng) or "text" (raw codepoints).
+    #[serde(default = "default_phoneme_type")]
+    pub phoneme_type: String,
+    /// Phoneme → model input ids.
+    #[serde(default)]
+    pub phoneme_id_map: HashMap<String, Vec<i64>>,
+    /// Size of the model's input vocabulary.
+    #[serde(default)]
+    pub num_symbols: usize,
+    #[serde(default = "default_num_speakers")]
+    pub num_speakers: usize,
+    /// Speaker name → id, for multi-speaker voices.
+    #[serde(default)]
+    pub speaker_id_map: HashMap<String, i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AudioConfig {
+    pub sample_rate: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LanguageConfig {
+    /// Locale, e.g. "en_US".
+    pub code: String,
+    #[serde(default)]
+    pub name_english: Option<String>,
+    #[serde(default)]
+    pub country_english: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EspeakConfig {
+    /// espeak-ng voice used for phonemization, e.g. "en-us".
+    pub voice: String,
+}
+
+fn default_phoneme_type() -> String {
+    "espeak".to_string()
+}
+
+fn default_num_speakers() -> usize {
+    1
+}
+
+impl PiperVoiceConfig {
+    /// Check the config describes a voice Piper can run, listing every problem found.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+
+        if !SAMPLE_RATES.contains(&self.audio.sample_rate) {
+            problems.push(format!(
+                "audio.sample_rate {} is not a Piper rate (expected one of {:?})",
+                self.audio.sample_rate, SAMPLE_RATES
+            ));
+        }
+
+        match self.phoneme_type.as_str() {
+            "espeak" => {
+                if self.espeak.as_ref().is_none_or(|e| e.voice.trim().is_empty()) {
+                    problems.push("phoneme_type is espeak but espeak.voice is missing".to_string());
+                }
+            }
+            "text" => {}
+            other => problems.push(format!("unknown phoneme_type '{}'", other)),
+        }
+
+        if self.phoneme_id_map.is_empty() {
+            problems.push("phoneme_id_map is empty".to_string());
+        } else if self.num_symbols > 0 {
+            let max_id = self.phoneme_id_map.values().flatten().copied().max().unwrap_or(0);
+            if max_id >= self.num_symbols as i64 {
+                problems.push(format!(
+                    "phoneme_id_map uses id {} but num_symbols is {}",
+                    max_id, self.num_symbols
+                ));
+            }
+        }
+
+        if self.num_speakers == 0 {
+            problems.push("num_speakers is 0".to_string());
+        }
+        if let Some((name, id)) = self
+            .speaker_id_map
+            .iter()
+            .find(|(_, id)| **id < 0 || **id as usize >= self.num_speakers)
+        {
+            problems.push(format!(
+                "speaker '{}' has id {} but num_speakers is {}",
+                name, id, self.num_speakers
+            ));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// An installed Piper voice: `<id>.onnx` with its `<id>.onnx.json` config.
+#[derive(Debug, Clone)]
+pub struct PiperVoice {
+    pub info: VoiceInfo,
+    pub model_path: PathBuf,
+    pub config: PiperVoiceConfig,
+}
+
+impl PiperVoice {
+    /// Load and validate the voice at `model_path`.
+    pub fn load(model_path: &Path) -> anyhow::Result<Self> {
+        // Model files are named like `en_US-amy-medium.onnx`
+        let id = model_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow::anyhow!("bad voice file name"))?;
+
+        let model_size = std::fs::metadata(model_path)
+            .map_err(|e| anyhow::anyhow!("cannot read model: {}", e))?
+            .len();
+        if model_size == 0 {
+            anyhow::bail!("model file is empty");
+        }
+
+        let config_path = PathBuf::from(format!("{}.json", model_path.display()));
+        let json = std::fs::read_to_string(&config_path).map_err(|e| {
+            anyhow::anyhow!("cannot read {} ({}); every voice needs its .onnx.json", config_path.display(), e)
+        })?;
+        let config: PiperVoiceConfig = serde_json::from_str(&json)
+            .map_err(|e| anyhow::anyhow!("invalid {}: {}", config_path.display(), e))?;
+        config
+            .validate()
+            .map_err(|problems| anyhow::anyhow!("{}", problems.join("; ")))?;
+
+        let language = config.language.as_ref();
+        let code = language
+            .map(|l| l.code.clone())
+            .unwrap_or_else(|| id.split('-').next().unwrap_or_default().to_string());
+        let language_name = match language.and_then(|l| l.name_english.as_deref()) {
+            Some(name) => match language.and_then(|l| l.country_english.as_deref()) {
+                Some(country) => format!("{} ({})", name, country),
+                None => name.to_string(),
+            },
+            None => code.clone(),
+        };
+
+        let mut speakers: Vec<Speaker> = config
+            .speaker_id_map
+            .iter()
+            .map(|(name, id)| Speaker {
+                id: *id,
+                name: name.clone(),
+            })
+            .collect();
+        speakers.sort_by_key(|s| s.id);
+        if config.num_speakers < 2 {
+            speakers.clear();
+        } else if speakers.is_empty() {
+            // Unnamed speakers are still selectable by number
+            speakers = (0..config.num_speakers as i64)
+                .map(|id| Speaker {
+                    id,
+                    name: format!("#{}", id),
+                })
+                .collect();
+        }
+
+        Ok(Self {
+            info: VoiceInfo {
+                id,
+                language: code,
+                language_name,
+                speakers,
+            },
+            model_path: model_path.to_path_buf(),
+            config,
+        })
+    }
+
+    /// Rate of the raw PCM Piper writes for this voice.
+    pub fn sample_rate(&self) -> u32 {
+        self.config.audio.sample_rate
+    }
+
+    /// ISO 639-1 part of the locale ("en" for "en_US").
+    pub fn iso_language(&self) -> String {
+        self.info
+            .language
+            .split(['_', '-'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase()
+    }
+}
@@ -0,0 +1,556 @@
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::agent::tools::{ToolCall, ToolDefinition};
+use crate::ai::llm::{ChatMessage, GroqUsage, LlmResponse, LlmStream, StreamEvent};
+use crate::config::AppConfig;
+
+/// A chat completion backend. Implementations translate `ChatMessage`s and
+/// `ToolDefinition`s into their wire format and back.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Short name used in logs and error messages (e.g. "groq", "local").
+    fn name(&self) -> &str;
+
+    /// Run a completion and return the full response.
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        model: &str,
+        tools: &[ToolDefinition],
+    ) -> anyhow::Result<LlmResponse>;
+
+    /// Run a completion, streaming text deltas as they are generated.
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        model: &str,
+        tools: &[ToolDefinition],
+    ) -> anyhow::Result<LlmStream>;
+}
+
+/// An error response from an LLM provider, with any rate-limit hints it sent.
+#[derive(Debug)]
+pub struct ApiError {
+    pub provider: String,
+    pub status: StatusCode,
+    /// Delay requested via `Retry-After` or Groq's `x-ratelimit-reset-*` headers.
+    pub retry_after: Option<Duration>,
+    pub body: String,
+}
+
+impl ApiError {
+    /// Rate limits and server errors are worth retrying; other 4xx are not.
+    pub fn is_retryable(&self) -> bool {
+        self.status == StatusCode::TOO_MANY_REQUESTS || self.status.is_server_error()
+    }
+
+    /// Bad credentials won't be fixed by retrying or switching models.
+    pub fn is_auth_error(&self) -> bool {
+        self.status == StatusCode::UNAUTHORIZED || self.status == StatusCode::FORBIDDEN
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} API error ({}): {}", self.provider, self.status, self.body)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Any server speaking the OpenAI chat completions API: Groq, OpenAI,
+/// llama.cpp `server`, Ollama, vLLM, ...
+pub struct OpenAiCompatibleProvider {
+    name: String,
+    client: Client,
+    /// Base URL up to and including the API version, e.g. `https://api.groq.com/openai/v1`.
+    base_url: String,
+    api_key: String,
+    /// Header carrying the API key (usually `Authorization`).
+    auth_header: String,
+    /// Prefix for the key in `auth_header` (usually `Bearer`); empty sends the raw key.
+    auth_scheme: String,
+    /// Extra top-level request fields merged into every request body.
+    extra_params: serde_json::Value,
+}
+
+impl OpenAiCompatibleProvider {
+    /// Build the provider described by the `LLM_*` settings in `AppConfig`.
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            name: config.llm_provider.clone(),
+            client: Client::builder()
+                .timeout(std::time::Duration::from_secs(60))
+                .build()
+                .unwrap_or_default(),
+            base_url: config.llm_base_url.trim_end_matches('/').to_string(),
+            api_key: config.llm_api_key.clone(),
+            auth_header: config.llm_auth_header.clone(),
+            auth_scheme: config.llm_auth_scheme.clone(),
+            extra_params: config.llm_extra_params.clone(),
+        }
+    }
+
+    /// Build the request body, applying the configured extra params.
+    fn request_body(
+        &self,
+        messages: &[ChatMessage],
+        model: &str,
+        tools: &[ToolDefinition],
+    ) -> serde_json::Value {
+        let mut body = build_request_body(messages, model, tools);
+        if let (Some(body_obj), Some(extra)) = (body.as_object_mut(), self.extra_params.as_object()) {
+            for (key, value) in extra {
+                body_obj.insert(key.clone(), value.clone());
+            }
+        }
+        body
+    }
+
+    /// POST a request body to the chat completions endpoint and check the status.
+    async fn send(&self, body: &serde_json::Value) -> anyhow::Result<reqwest::Response> {
+        let mut req = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .header("Content-Type", "application/json")
+            .json(body);
+
+        // Local servers usually run without auth
+        if !self.api_key.is_empty() {
+            let value = if self.auth_scheme.is_empty() {
+                self.api_key.clone()
+            } else {
+                format!("{} {}", self.auth_scheme, self.api_key)
+            };
+            req = req.header(self.auth_header.as_str(), value);
+        }
+
+        let resp = req.send().await?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let retry_after = retry_delay_from_headers(resp.headers());
+            let err_body = resp.text().await.unwrap_or_default();
+            return Err(ApiError {
+                provider: self.name.clone(),
+                status,
+                retry_after,
+                body: err_body,
+            }
+            .into());
+        }
+
+        Ok(resp)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiCompatibleProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        model: &str,
+        tools: &[ToolDefinition],
+    ) -> anyhow::Result<LlmResponse> {
+        let body = self.request_body(messages, model, tools);
+        let resp = self.send(&body).await?;
+        let api_resp: ApiResponse = resp.json().await?;
+
+        let (text, tool_calls) = match api_resp.choices.into_iter().next() {
+            Some(choice) => {
+                let tool_calls = choice
+                    .message
+                    .tool_calls
+                    .into_iter()
+                    .map(parse_tool_call)
+                    .collect();
+                (choice.message.content.unwrap_or_default(), tool_calls)
+            }
+            None => (String::new(), Vec::new()),
+        };
+
+        Ok(LlmResponse {
+            text,
+            tool_calls,
+            usage: api_resp.usage,
+            model: model.to_string(),
+        })
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        model: &str,
+        tools: &[ToolDefinition],
+    ) -> anyhow::Result<LlmStream> {
+        let mut body = self.request_body(messages, model, tools);
+        body["stream"] = serde_json::json!(true);
+        body["stream_options"] = serde_json::json!({ "include_usage": true });
+
+        let resp = self.send(&body).await?;
+        let (tx, rx) = mpsc::channel(64);
+        let model = model.to_string();
+
+        tokio::spawn(async move {
+            if let Err(e) = read_sse_stream(resp, model, &tx).await {
+                let _ = tx.send(Err(e)).await;
+            }
+        });
+
+        Ok(rx)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ApiMessage {
+    role: String,
+    /// Null for assistant messages that only carry tool calls.
+    content: Option<ApiContent>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<ApiToolCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+/// Plain text, or text plus images as content parts.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+enum ApiContent {
+    Text(String),
+    Parts(Vec<ApiContentPart>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ApiContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ApiImageUrl },
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ApiImageUrl {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    choices: Vec<ApiChoice>,
+    usage: Option<GroqUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiChoice {
+    message: ApiMessageContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiMessageContent {
+    /// Null when the model only returns tool calls.
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ApiToolCall>,
+}
+
+/// OpenAI-style tool definition sent in the request's `tools` array.
+#[derive(Debug, Serialize)]
+struct ApiTool<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: &'a ToolDefinition,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ApiToolCall {
+    id: String,
+    #[serde(rename = "type", default = "function_kind")]
+    kind: String,
+    function: ApiFunctionCall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ApiFunctionCall {
+    name: String,
+    /// JSON-encoded arguments, as a string.
+    arguments: String,
+}
+
+/// One `data:` chunk of a streamed completion.
+#[derive(Debug, Deserialize)]
+struct ApiStreamChunk {
+    #[serde(default)]
+    choices: Vec<ApiStreamChoice>,
+    usage: Option<GroqUsage>,
+    /// Groq reports usage here instead of the top-level `usage` field.
+    x_groq: Option<ApiStreamExtra>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiStreamChoice {
+    delta: ApiStreamDelta,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiStreamDelta {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ApiToolCallDelta>,
+}
+
+/// A fragment of a tool call; fragments with the same `index` are concatenated.
+#[derive(Debug, Deserialize)]
+struct ApiToolCallDelta {
+    index: usize,
+    id: Option<String>,
+    function: Option<ApiFunctionCallDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiFunctionCallDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiStreamExtra {
+    usage: Option<GroqUsage>,
+}
+
+/// Convert a wire-format tool call into a `ToolCall`, decoding its JSON arguments.
+fn parse_tool_call(call: ApiToolCall) -> ToolCall {
+    // Tools without parameters may come back with an empty arguments string
+    let raw = match call.function.arguments.trim() {
+        "" => "{}",
+        args => args,
+    };
+    let arguments = serde_json::from_str(raw).unwrap_or_else(|e| {
+        tracing::warn!(
+            "Tool call '{}' has invalid JSON arguments ({}): {}",
+            call.function.name,
+            e,
+            call.function.arguments
+        );
+        serde_json::json!({})
+    });
+
+    ToolCall {
+        id: call.id,
+        name: call.function.name,
+        arguments,
+    }
+}
+
+/// Convert a `ToolCall` back into wire format for replaying it in the history.
+fn to_wire_tool_call(call: &ToolCall) -> ApiToolCall {
+    ApiToolCall {
+        id: call.id.clone(),
+        kind: function_kind(),
+        function: ApiFunctionCall {
+            name: call.name.clone(),
+            arguments: call.arguments.to_string(),
+        },
+    }
+}
+
+fn function_kind() -> String {
+    "function".to_string()
+}
+
+/// Build the chat completions request body shared by the streaming and blocking paths.
+fn build_request_body(
+    messages: &[ChatMessage],
+    model: &str,
+    tools: &[ToolDefinition],
+) -> serde_json::Value {
+    let api_messages: Vec<ApiMessage> = messages
+        .iter()
+        .map(|m| ApiMessage {
+            role: m.role.clone(),
+            content: if m.content.is_empty() && !m.tool_calls.is_empty() {
+                None
+            } else if !m.images.is_empty() {
+                let mut parts = vec![ApiContentPart::Text { text: m.content.clone() }];
+                parts.extend(m.images.iter().map(|url| ApiContentPart::ImageUrl {
+                    image_url: ApiImageUrl { url: url.clone() },
+                }));
+                Some(ApiContent::Parts(parts))
+            } else {
+                Some(ApiContent::Text(m.content.clone()))
+            },
+            tool_calls: m.tool_calls.iter().map(to_wire_tool_call).collect(),
+            tool_call_id: m.tool_call_id.clone(),
+        })
+        .collect();
+
+    let mut body = serde_json::json!({
+        "model": model,
+        "messages": api_messages,
+        "temperature": 0.7,
+        "max_tokens": 2048,
+    });
+
+    if !tools.is_empty() {
+        let api_tools: Vec<ApiTool> = tools
+            .iter()
+            .map(|t| ApiTool {
+                kind: "function",
+                function: t,
+            })
+            .collect();
+        body["tools"] = serde_json::json!(api_tools);
+        body["tool_choice"] = serde_json::json!("auto");
+    }
+
+    body
+}
+
+/// Read a server-sent events body, forwarding text deltas and assembling the final response.
+async fn read_sse_stream(
+    resp: reqwest::Response,
+    model: String,
+    tx: &mpsc::Sender<anyhow::Result<StreamEvent>>,
+) -> anyhow::Result<()> {
+    let mut bytes = resp.bytes_stream();
+    // Raw bytes are buffered until a full line arrives, so multi-byte
+    // characters split across network chunks are decoded correctly.
+    let mut buf: Vec<u8> = Vec::new();
+    let mut text = String::new();
+    let mut partial_calls: Vec<ApiToolCall> = Vec::new();
+    let mut usage = None;
+
+    'outer: while let Some(chunk) = bytes.next().await {
+        buf.extend_from_slice(&chunk?);
+
+        while let Some(newline) = buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buf.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let data = data.trim();
+            if data == "[DONE]" {
+                break 'outer;
+            }
+
+            let chunk: ApiStreamChunk = match serde_json::from_str(data) {
+                Ok(c) => c,
+                Err(e) => {
+                    tracing::warn!("Skipping malformed stream chunk ({}): {}", e, data);
+                    continue;
+                }
+            };
+
+            if let Some(u) = chunk.usage.or(chunk.x_groq.and_then(|x| x.usage)) {
+                usage = Some(u);
+            }
+
+            for choice in chunk.choices {
+                if let Some(delta) = choice.delta.content.filter(|d| !d.is_empty()) {
+                    text.push_str(&delta);
+                    if tx.send(Ok(StreamEvent::Delta(delta))).await.is_err() {
+                        // Receiver dropped — nobody is listening any more
+                        return Ok(());
+                    }
+                }
+
+                for call in choice.delta.tool_calls {
+                    while partial_calls.len() <= call.index {
+                        partial_calls.push(ApiToolCall {
+                            id: String::new(),
+                            kind: function_kind(),
+                            function: ApiFunctionCall {
+                                name: String::new(),
+                                arguments: String::new(),
+                            },
+                        });
+                    }
+                    let slot = &mut partial_calls[call.index];
+                    if let Some(id) = call.id {
+                        slot.id = id;
+                    }
+                    if let Some(function) = call.function {
+                        if let Some(name) = function.name {
+                            slot.function.name.push_str(&name);
+                        }
+                        if let Some(args) = function.arguments {
+                            slot.function.arguments.push_str(&args);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let response = LlmResponse {
+        text,
+        tool_calls: partial_calls.into_iter().map(parse_tool_call).collect(),
+        usage,
+        model,
+    };
+    let _ = tx.send(Ok(StreamEvent::Done(response))).await;
+    Ok(())
+}
+
+/// Work out how long the server wants us to wait before retrying.
+/// Prefers `Retry-After`; otherwise uses Groq's reset header for whichever
+/// limit (requests or tokens) is exhausted.
+fn retry_delay_from_headers(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(secs) = header("retry-after").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return Some(Duration::from_secs_f64(secs.max(0.0)));
+    }
+
+    [("requests", "x-ratelimit-reset-requests"), ("tokens", "x-ratelimit-reset-tokens")]
+        .iter()
+        .filter(|(kind, _)| header(&format!("x-ratelimit-remaining-{}", kind)) == Some("0"))
+        .filter_map(|(_, reset)| header(reset).and_then(parse_reset_duration))
+        .max()
+}
+
+/// Parse Groq's reset durations such as `"7.66s"`, `"2m59.56s"`, `"1h2m"` or `"250ms"`.
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut number = String::new();
+    let mut chars = value.trim().chars().peekable();
+    let mut parsed_any = false;
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+
+        let n: f64 = number.parse().ok()?;
+        number.clear();
+        let unit_secs = match c {
+            'h' => 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                0.001
+            }
+            'm' => 60.0,
+            's' => 1.0,
+            _ => return None,
+        };
+        total += n * unit_secs;
+        parsed_any = true;
+    }
+
+    // A bare number is treated as seconds
+    if !number.is_empty() {
+        total += number.parse::<f64>().ok()?;
+        parsed_any = true;
+    }
+
+    parsed_any.then(|| Duration::from_secs_f64(total))
+}
@@ -0,0 +1,75 @@
+//! Splitting replies into sentence-sized pieces for TTS, so long replies are
+//! spoken in full instead of being cut off.
+
+/// Split `text` into chunks of whole sentences of at most `max_chars` characters.
+/// Sentences longer than that are cut after a comma, semicolon or colon, else at a space.
+pub fn split_for_speech(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut pieces = Vec::new();
+    for sentence in sentences(text) {
+        split_long(sentence, max_chars, &mut pieces);
+    }
+
+    let mut chunks: Vec<String> = Vec::new();
+    for piece in pieces {
+        match chunks.last_mut() {
+            Some(chunk) if chunk.chars().count() + 2 + piece.chars().count() <= max_chars => {
+                // A line without final punctuation (list item, heading) still gets its pause
+                if chunk.ends_with(char::is_alphanumeric) {
+                    chunk.push('.');
+                }
+                chunk.push(' ');
+                chunk.push_str(&piece);
+            }
+            _ => chunks.push(piece),
+        }
+    }
+    chunks
+}
+
+/// Sentences of `text`: ending in `.`, `!`, `?` or `…` followed by whitespace,
+/// a CJK full stop, or a line break.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let boundary = match c {
+            '\n' | '。' | '！' | '？' => true,
+            // "3.5" and "e.g.," don't end a sentence
+            '.' | '!' | '?' | '…' => chars.peek().is_none_or(|(_, next)| next.is_whitespace()),
+            _ => false,
+        };
+        if boundary {
+            let end = i + c.len_utf8();
+            sentences.push(text[start..end].trim());
+            start = end;
+        }
+    }
+    sentences.push(text[start..].trim());
+
+    sentences.retain(|s| !s.is_empty());
+    sentences
+}
+
+/// Push `sentence` to `out` in pieces of at most `max_chars` characters.
+fn split_long(sentence: &str, max_chars: usize, out: &mut Vec<String>) {
+    let mut rest = sentence;
+    while rest.chars().count() > max_chars {
+        // Byte offset just past the first `max_chars` characters
+        let limit = rest.char_indices().nth(max_chars).map_or(rest.len(), |(i, _)| i);
+        let head = &rest[..limit];
+        let cut = head
+            .rfind([',', ';', ':'])
+            .map(|i| i + 1)
+            .or_else(|| head.rfind(char::is_whitespace))
+            .filter(|&i| i > 0)
+            .unwrap_or(limit);
+        out.push(rest[..cut].trim().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        out.push(rest.to_string());
+    }
+}
@@ -1,58 +1,329 @@
-use std::path::Path;
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use whisper_rs::{FullParams, SamplingStrategy};
 
-pub struct SttEngine {
-    ctx: WhisperContext,
+use crate::ai::chunking::Window;
+use crate::ai::stt_backend::{stitch_windows, SttBackend};
+use crate::ai::whisper_models::{LoadedModel, ModelRegistry, ModelTier};
+
+/// The user cancelled the transcription.
+#[derive(Debug)]
+pub struct TranscriptionCancelled;
+
+impl fmt::Display for TranscriptionCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "transcription cancelled")
+    }
 }
 
-impl SttEngine {
-    pub fn new(model_path: &str) -> anyhow::Result<Self> {
-        if !Path::new(model_path).exists() {
-            anyhow::bail!(
-                "Whisper model not found at '{}'. Download it from: \
-                 https://huggingface.co/ggerganov/whisper.cpp/tree/main",
-                model_path
-            );
+impl std::error::Error for TranscriptionCancelled {}
+
+/// Language setting value that lets whisper detect the spoken language.
+pub const AUTO_LANGUAGE: &str = "auto";
+
+/// Languages offered in /settings: (whisper code, button label).
+pub const STT_LANGUAGES: &[(&str, &str)] = &[
+    (AUTO_LANGUAGE, "🌐 Auto"),
+    ("en", "🇬🇧 English"),
+    ("ru", "🇷🇺 Русский"),
+    ("uz", "🇺🇿 O'zbek"),
+];
+
+/// Human-readable label for an STT language code.
+pub fn language_label(code: &str) -> &str {
+    STT_LANGUAGES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, label)| *label)
+        .unwrap_or(code)
+}
+
+/// Longest initial prompt passed to whisper; it only looks at the last ~224 tokens anyway.
+const MAX_PROMPT_CHARS: usize = 800;
+
+/// How whisper searches for the most likely transcript.
+#[derive(Debug, Clone, Copy)]
+pub enum Sampling {
+    /// Pick the best of `best_of` greedy candidates.
+    Greedy { best_of: i32 },
+    /// Beam search: slower, usually more accurate.
+    BeamSearch { beam_size: i32 },
+}
+
+impl Sampling {
+    /// "beam" (or "beam_search") selects beam search; anything else is greedy.
+    pub fn from_name(name: &str, beam_size: i32, best_of: i32) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "beam" | "beam_search" => Self::BeamSearch {
+                beam_size: beam_size.max(1),
+            },
+            _ => Self::Greedy {
+                best_of: best_of.max(1),
+            },
         }
+    }
+}
 
-        let ctx = WhisperContext::new_with_params(model_path, WhisperContextParameters::default())
-            .map_err(|e| anyhow::anyhow!("Failed to load whisper model: {}", e))?;
+/// Decoder settings applied to every transcription.
+#[derive(Debug, Clone)]
+pub struct DecodingOptions {
+    pub sampling: Sampling,
+    /// CPU threads per whisper call.
+    pub threads: usize,
+    /// Starting sampling temperature (0 = deterministic).
+    pub temperature: f32,
+    /// Added to the temperature each time a pass fails whisper's quality checks;
+    /// 0 disables the fallback.
+    pub temperature_inc: f32,
+}
+
+/// Build whisper's initial prompt from glossary terms, so names and jargon are
+/// spelled the way the user expects. Returns `None` when there are no terms.
+/// Later terms win: whisper only looks at the end of the prompt, so when the
+/// glossary is too long the earliest terms are dropped (callers list the user's
+/// own terms last).
+pub fn vocabulary_prompt<'a>(terms: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let terms: Vec<&str> = terms.into_iter().map(str::trim).collect();
 
-        tracing::info!("Whisper STT model loaded from '{}'", model_path);
-        Ok(Self { ctx })
+    // Walk from the end, keeping each term's last mention, until the prompt is full
+    let mut seen = std::collections::HashSet::new();
+    let mut kept: Vec<&str> = Vec::new();
+    let mut len = "Glossary: .".len();
+    for term in terms.into_iter().rev() {
+        if term.is_empty() || !seen.insert(term.to_lowercase()) {
+            continue;
+        }
+        let added = term.len() + if kept.is_empty() { 0 } else { ", ".len() };
+        if len + added > MAX_PROMPT_CHARS && !kept.is_empty() {
+            break;
+        }
+        len += added;
+        kept.push(term);
     }
+    if kept.is_empty() {
+        return None;
+    }
+    kept.reverse();
 
-    /// Transcribe raw PCM f32 audio data (16kHz mono) to text.
-    pub fn transcribe(&self, pcm_data: &[f32]) -> anyhow::Result<String> {
-        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
-        params.set_language(Some("en"));
+    // whisper.cpp can't take interior NUL bytes
+    let prompt = format!("Glossary: {}.", kept.join(", ")).replace('\0', "");
+    if prompt.len() <= MAX_PROMPT_CHARS {
+        return Some(prompt);
+    }
+    // A single oversized term: keep its end, like whisper would
+    let mut cut = prompt.len() - MAX_PROMPT_CHARS;
+    while !prompt.is_char_boundary(cut) {
+        cut += 1;
+    }
+    Some(prompt[cut..].to_string())
+}
+
+/// Result of transcribing one clip.
+pub struct Transcription {
+    /// All segment texts joined.
+    pub text: String,
+    /// ISO 639-1 code of the spoken language (detected when requested as "auto").
+    pub language: String,
+    pub segments: Vec<Segment>,
+}
+
+impl Transcription {
+    /// Mean token probability across the whole clip (0.0–1.0). Falls back to the
+    /// mean segment confidence when there are no token probabilities (remote backends).
+    pub fn confidence(&self) -> f32 {
+        let (sum, count) = self
+            .segments
+            .iter()
+            .flat_map(|s| &s.token_probabilities)
+            .fold((0.0, 0), |(sum, n), p| (sum + p, n + 1));
+        if count > 0 {
+            return sum / count as f32;
+        }
+
+        if self.segments.is_empty() {
+            0.0
+        } else {
+            self.segments.iter().map(|s| s.confidence).sum::<f32>() / self.segments.len() as f32
+        }
+    }
+}
+
+/// A stretch of speech whisper decoded as one unit.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    /// Start and end relative to the start of the clip, in milliseconds.
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+    /// Mean probability of the segment's text tokens (0.0–1.0).
+    pub confidence: f32,
+    /// Probability whisper assigned to each decoded text token.
+    pub token_probabilities: Vec<f32>,
+}
+
+/// What to transcribe with: everything about a job except the audio.
+#[derive(Debug, Clone)]
+pub struct TranscribeOptions {
+    /// Whisper language code, or "auto" to detect it.
+    pub language: String,
+    /// Vocabulary hint passed to whisper as the initial prompt.
+    pub prompt: Option<String>,
+    /// Requested speed/accuracy tier; the nearest available model is used.
+    pub tier: ModelTier,
+}
+
+pub struct SttEngine {
+    models: Arc<ModelRegistry>,
+    decoding: DecodingOptions,
+}
+
+impl SttEngine {
+    pub fn new(models: Arc<ModelRegistry>, decoding: DecodingOptions) -> Self {
+        Self { models, decoding }
+    }
+
+    /// The nearest installed model for the job's tier and language, loaded.
+    fn model_for(&self, options: &TranscribeOptions) -> anyhow::Result<LoadedModel> {
+        let key = self
+            .models
+            .resolve(options.tier, &options.language)
+            .ok_or_else(|| {
+                anyhow::anyhow!("No whisper model can transcribe '{}'", options.language)
+            })?;
+        self.models.get(key)
+    }
+
+    /// Transcribe raw PCM f32 audio data (16kHz mono) to text in a single whisper call.
+    /// `language` is a whisper language code, or "auto" to detect it.
+    fn transcribe_with(
+        &self,
+        model: &LoadedModel,
+        pcm_data: &[f32],
+        language: &str,
+        prompt: Option<&str>,
+        cancel: &Arc<AtomicBool>,
+    ) -> anyhow::Result<Transcription> {
+        let language = if model.multilingual { language } else { "en" };
+
+        let strategy = match self.decoding.sampling {
+            Sampling::Greedy { best_of } => SamplingStrategy::Greedy { best_of },
+            Sampling::BeamSearch { beam_size } => SamplingStrategy::BeamSearch {
+                beam_size,
+                patience: -1.0,
+            },
+        };
+        let mut params = FullParams::new(strategy);
+        params.set_language(Some(language));
+        params.set_temperature(self.decoding.temperature);
+        params.set_temperature_inc(self.decoding.temperature_inc);
+        if let Some(prompt) = prompt {
+            params.set_initial_prompt(prompt);
+        }
         params.set_print_special(false);
         params.set_print_progress(false);
         params.set_print_realtime(false);
         params.set_print_timestamps(false);
-        // Single-threaded for predictable performance on CPU
-        params.set_n_threads(2);
+        params.set_n_threads(self.decoding.threads.max(1) as i32);
+        let abort = cancel.clone();
+        params.set_abort_callback_safe(move || abort.load(Ordering::Relaxed));
 
-        let mut state = self
+        let mut state = model
             .ctx
             .create_state()
             .map_err(|e| anyhow::anyhow!("Failed to create whisper state: {}", e))?;
 
-        state
-            .full(params, pcm_data)
-            .map_err(|e| anyhow::anyhow!("Whisper transcription failed: {}", e))?;
+        if let Err(e) = state.full(params, pcm_data) {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(TranscriptionCancelled.into());
+            }
+            anyhow::bail!("Whisper transcription failed: {}", e);
+        }
 
         let num_segments = state.full_n_segments()
             .map_err(|e| anyhow::anyhow!("Failed to get segments: {}", e))?;
 
         let mut text = String::new();
+        let mut segments = Vec::with_capacity(num_segments.max(0) as usize);
         for i in 0..num_segments {
-            if let Ok(segment) = state.full_get_segment_text(i) {
-                text.push_str(&segment);
-                text.push(' ');
-            }
+            let Ok(segment_text) = state.full_get_segment_text(i) else {
+                continue;
+            };
+            text.push_str(&segment_text);
+            text.push(' ');
+
+            let n_tokens = state.full_n_tokens(i).unwrap_or(0);
+            let token_probabilities: Vec<f32> = (0..n_tokens)
+                .filter_map(|t| {
+                    let text = state.full_get_token_text(i, t).ok()?;
+                    // Skip control tokens such as [_BEG_], [_TT_150] and <|endoftext|>
+                    if text.starts_with("[_") || text.starts_with("<|") {
+                        return None;
+                    }
+                    state.full_get_token_prob(i, t).ok()
+                })
+                .collect();
+            let confidence = if token_probabilities.is_empty() {
+                0.0
+            } else {
+                token_probabilities.iter().sum::<f32>() / token_probabilities.len() as f32
+            };
+
+            // Whisper timestamps are in 10 ms units
+            segments.push(Segment {
+                start_ms: state.full_get_segment_t0(i).unwrap_or(0) * 10,
+                end_ms: state.full_get_segment_t1(i).unwrap_or(0) * 10,
+                text: segment_text.trim().to_string(),
+                confidence,
+                token_probabilities,
+            });
         }
 
-        Ok(text.trim().to_string())
+        let language = if language == AUTO_LANGUAGE {
+            state
+                .full_lang_id_from_state()
+                .ok()
+                .and_then(whisper_rs::get_lang_str)
+                .unwrap_or("en")
+        } else {
+            language
+        };
+
+        Ok(Transcription {
+            text: text.trim().to_string(),
+            language: language.to_string(),
+            segments,
+        })
+    }
+}
+
+impl SttBackend for SttEngine {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn transcribe(
+        &self,
+        pcm: &[f32],
+        options: &TranscribeOptions,
+        cancel: &Arc<AtomicBool>,
+    ) -> anyhow::Result<Transcription> {
+        let model = self.model_for(options)?;
+        self.transcribe_with(&model, pcm, &options.language, options.prompt.as_deref(), cancel)
+    }
+
+    /// The model is resolved and loaded once, so every window of a job uses the same one.
+    fn transcribe_windows(
+        &self,
+        pcm: &[f32],
+        windows: &[Window],
+        options: &TranscribeOptions,
+        cancel: &Arc<AtomicBool>,
+        on_progress: &mut dyn FnMut(usize),
+    ) -> anyhow::Result<Transcription> {
+        let model = self.model_for(options)?;
+        stitch_windows(pcm, windows, options, cancel, on_progress, |audio, options| {
+            self.transcribe_with(&model, audio, &options.language, options.prompt.as_deref(), cancel)
+        })
     }
 }
@@ -0,0 +1,373 @@
+use reqwest::multipart::{Form, Part};
+use reqwest::Client;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+
+use crate::ai::chunking::{self, Window};
+use crate::ai::stt::{Segment, TranscribeOptions, Transcription, TranscriptionCancelled, AUTO_LANGUAGE};
+use crate::audio::{wav, WHISPER_SAMPLE_RATE};
+use crate::config::AppConfig;
+
+/// Shortest input whisper.cpp accepts: one second at 16 kHz.
+const MIN_INPUT_SAMPLES: usize = 16_000;
+/// How long a failed remote backend is skipped before it is tried again.
+const REMOTE_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+fn samples_to_ms(samples: usize) -> i64 {
+    (samples / 16) as i64
+}
+
+/// A speech-to-text engine. Called on transcription worker threads, so
+/// implementations may block; each call handles one window of audio.
+pub trait SttBackend: Send + Sync {
+    /// Short name used in logs (e.g. "local", "whisper.cpp").
+    fn name(&self) -> &str;
+
+    /// Transcribe 16 kHz mono PCM in one pass, giving up once `cancel` is set.
+    fn transcribe(
+        &self,
+        pcm: &[f32],
+        options: &TranscribeOptions,
+        cancel: &Arc<AtomicBool>,
+    ) -> anyhow::Result<Transcription>;
+
+    /// Transcribe `windows` of `pcm` one after another and stitch them into one
+    /// transcript, with segment times relative to the start of `pcm`.
+    /// In auto mode the language detected in the first window is kept for the rest.
+    /// `on_progress` gets the number of finished windows; setting `cancel` stops
+    /// the job, interrupting the window in flight.
+    fn transcribe_windows(
+        &self,
+        pcm: &[f32],
+        windows: &[Window],
+        options: &TranscribeOptions,
+        cancel: &Arc<AtomicBool>,
+        on_progress: &mut dyn FnMut(usize),
+    ) -> anyhow::Result<Transcription> {
+        stitch_windows(pcm, windows, options, cancel, on_progress, |audio, options| {
+            self.transcribe(audio, options, cancel)
+        })
+    }
+}
+
+/// The body of [`SttBackend::transcribe_windows`], with each window passed to
+/// `transcribe`, so a backend can set up per-job state (such as the model) once.
+pub fn stitch_windows(
+    pcm: &[f32],
+    windows: &[Window],
+    options: &TranscribeOptions,
+    cancel: &Arc<AtomicBool>,
+    on_progress: &mut dyn FnMut(usize),
+    mut transcribe: impl FnMut(&[f32], &TranscribeOptions) -> anyhow::Result<Transcription>,
+) -> anyhow::Result<Transcription> {
+    let mut options = options.clone();
+    let mut segments = Vec::new();
+
+    for (i, window) in windows.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(TranscriptionCancelled.into());
+        }
+
+        let mut audio = pcm[window.range.clone()].to_vec();
+        // whisper.cpp refuses input under one second; pad with silence
+        if audio.len() < MIN_INPUT_SAMPLES {
+            audio.resize(MIN_INPUT_SAMPLES, 0.0);
+        }
+
+        let part = transcribe(&audio, &options)?;
+        options.language = part.language;
+
+        let offset_ms = samples_to_ms(window.range.start);
+        let shifted = part
+            .segments
+            .into_iter()
+            .map(|mut s| {
+                s.start_ms += offset_ms;
+                s.end_ms += offset_ms;
+                s
+            })
+            .collect();
+        let overlap_ms = offset_ms..samples_to_ms(window.range.start + window.overlap);
+        chunking::stitch(&mut segments, shifted, overlap_ms);
+
+        on_progress(i + 1);
+    }
+
+    let text = segments
+        .iter()
+        .map(|s| s.text.as_str())
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Ok(Transcription {
+        text,
+        language: options.language,
+        segments,
+    })
+}
+
+/// Wire protocol of a remote transcription server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RemoteApi {
+    /// whisper.cpp `server`: `POST /inference`.
+    WhisperCpp,
+    /// OpenAI-compatible: `POST /audio/transcriptions` (OpenAI, Groq, faster-whisper-server, ...).
+    OpenAi,
+}
+
+/// Transcription on another machine over HTTP.
+pub struct RemoteSttBackend {
+    name: String,
+    api: RemoteApi,
+    client: Client,
+    /// Server root for whisper.cpp, or base URL including `/v1` for OpenAI-compatible APIs.
+    base_url: String,
+    api_key: String,
+    /// Model name sent to OpenAI-compatible APIs.
+    model: String,
+    /// Runtime the blocking `transcribe` calls drive requests on.
+    runtime: Handle,
+}
+
+/// `verbose_json` response, as returned by both whisper.cpp and OpenAI.
+#[derive(Deserialize)]
+struct VerboseTranscription {
+    text: String,
+    /// Full language name ("english") or ISO code, depending on the server.
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default)]
+    segments: Vec<VerboseSegment>,
+}
+
+#[derive(Deserialize)]
+struct VerboseSegment {
+    /// Seconds.
+    start: f64,
+    end: f64,
+    text: String,
+    #[serde(default)]
+    avg_logprob: Option<f32>,
+}
+
+impl RemoteSttBackend {
+    /// Build the backend described by the `STT_*` settings, or `None` for local
+    /// transcription. Must be called inside the tokio runtime.
+    pub fn from_config(config: &AppConfig) -> anyhow::Result<Option<Self>> {
+        let api = match config.stt_backend.as_str() {
+            "local" | "" => return Ok(None),
+            "whisper_cpp" | "whisper.cpp" => RemoteApi::WhisperCpp,
+            "openai" => RemoteApi::OpenAi,
+            other => anyhow::bail!("Unknown STT_BACKEND '{}' (expected local, whisper_cpp or openai)", other),
+        };
+        if config.stt_remote_url.is_empty() {
+            anyhow::bail!("STT_REMOTE_URL is required for STT_BACKEND '{}'", config.stt_backend);
+        }
+
+        Ok(Some(Self {
+            name: match api {
+                RemoteApi::WhisperCpp => "whisper.cpp".to_string(),
+                RemoteApi::OpenAi => "openai".to_string(),
+            },
+            api,
+            client: Client::builder()
+                .timeout(Duration::from_secs(config.stt_remote_timeout_secs))
+                .build()
+                .unwrap_or_default(),
+            base_url: config.stt_remote_url.trim_end_matches('/').to_string(),
+            api_key: config.stt_remote_api_key.clone(),
+            model: config.stt_remote_model.clone(),
+            runtime: Handle::current(),
+        }))
+    }
+
+    async fn request(&self, pcm: &[f32], options: &TranscribeOptions) -> anyhow::Result<Transcription> {
+        let file = Part::bytes(wav::f32_to_wav(pcm, WHISPER_SAMPLE_RATE))
+            .file_name("audio.wav")
+            .mime_str("audio/wav")?;
+        let mut form = Form::new()
+            .part("file", file)
+            .text("response_format", "verbose_json");
+
+        // whisper.cpp wants "auto" spelled out; OpenAI detects when it's omitted
+        if options.language != AUTO_LANGUAGE || self.api == RemoteApi::WhisperCpp {
+            form = form.text("language", options.language.clone());
+        }
+        if let Some(prompt) = &options.prompt {
+            form = form.text("prompt", prompt.clone());
+        }
+
+        let url = match self.api {
+            RemoteApi::WhisperCpp => format!("{}/inference", self.base_url),
+            RemoteApi::OpenAi => {
+                form = form.text("model", self.model.clone());
+                format!("{}/audio/transcriptions", self.base_url)
+            }
+        };
+
+        let mut req = self.client.post(&url).multipart(form);
+        if !self.api_key.is_empty() {
+            req = req.bearer_auth(&self.api_key);
+        }
+
+        let resp = req.send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            anyhow::bail!("{} STT error ({}): {}", self.name, status, body);
+        }
+        let body: VerboseTranscription = resp.json().await?;
+
+        let language = body
+            .language
+            .as_deref()
+            .and_then(|l| whisper_rs::get_lang_id(&l.to_lowercase()))
+            .and_then(whisper_rs::get_lang_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| match options.language.as_str() {
+                AUTO_LANGUAGE => "en".to_string(),
+                requested => requested.to_string(),
+            });
+
+        let segments = body
+            .segments
+            .into_iter()
+            .map(|s| Segment {
+                start_ms: (s.start * 1000.0) as i64,
+                end_ms: (s.end * 1000.0) as i64,
+                text: s.text.trim().to_string(),
+                confidence: s.avg_logprob.map(f32::exp).unwrap_or(0.0),
+                token_probabilities: Vec::new(),
+            })
+            .collect();
+
+        Ok(Transcription {
+            text: body.text.trim().to_string(),
+            language,
+            segments,
+        })
+    }
+}
+
+impl SttBackend for RemoteSttBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn transcribe(
+        &self,
+        pcm: &[f32],
+        options: &TranscribeOptions,
+        cancel: &Arc<AtomicBool>,
+    ) -> anyhow::Result<Transcription> {
+        self.runtime.block_on(async {
+            tokio::select! {
+                result = self.request(pcm, options) => result,
+                _ = cancelled(cancel) => Err(TranscriptionCancelled.into()),
+            }
+        })
+    }
+}
+
+/// Resolves once `flag` is set.
+async fn cancelled(flag: &AtomicBool) {
+    while !flag.load(Ordering::Relaxed) {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+/// Use `primary` (a remote server), falling back to `fallback` (local whisper)
+/// when it fails. After a failure the primary is skipped for a while.
+pub struct FallbackSttBackend {
+    primary: Box<dyn SttBackend>,
+    fallback: Box<dyn SttBackend>,
+    /// When the primary last failed.
+    failed_at: Mutex<Option<Instant>>,
+}
+
+impl FallbackSttBackend {
+    pub fn new(primary: Box<dyn SttBackend>, fallback: Box<dyn SttBackend>) -> Self {
+        Self {
+            primary,
+            fallback,
+            failed_at: Mutex::new(None),
+        }
+    }
+}
+
+impl FallbackSttBackend {
+    /// Whether the primary failed recently and should be skipped.
+    fn skip_primary(&self) -> bool {
+        self.failed_at
+            .lock()
+            .unwrap()
+            .is_some_and(|t| t.elapsed() < REMOTE_RETRY_AFTER)
+    }
+
+    /// Record the outcome of a primary call; `true` if the fallback should take over.
+    fn primary_failed<T>(&self, result: &anyhow::Result<T>) -> bool {
+        match result {
+            Ok(_) => {
+                *self.failed_at.lock().unwrap() = None;
+                false
+            }
+            Err(e) if e.is::<TranscriptionCancelled>() => false,
+            Err(e) => {
+                tracing::warn!(
+                    "{} STT failed ({}), using {} for the next {}s",
+                    self.primary.name(),
+                    e,
+                    self.fallback.name(),
+                    REMOTE_RETRY_AFTER.as_secs()
+                );
+                *self.failed_at.lock().unwrap() = Some(Instant::now());
+                true
+            }
+        }
+    }
+}
+
+impl SttBackend for FallbackSttBackend {
+    fn name(&self) -> &str {
+        self.primary.name()
+    }
+
+    fn transcribe(
+        &self,
+        pcm: &[f32],
+        options: &TranscribeOptions,
+        cancel: &Arc<AtomicBool>,
+    ) -> anyhow::Result<Transcription> {
+        if !self.skip_primary() {
+            let result = self.primary.transcribe(pcm, options, cancel);
+            if !self.primary_failed(&result) {
+                return result;
+            }
+        }
+        self.fallback.transcribe(pcm, options, cancel)
+    }
+
+    /// The backend is chosen once for the whole job: if the primary fails part
+    /// way, the job starts over on the fallback rather than mixing the two.
+    fn transcribe_windows(
+        &self,
+        pcm: &[f32],
+        windows: &[Window],
+        options: &TranscribeOptions,
+        cancel: &Arc<AtomicBool>,
+        on_progress: &mut dyn FnMut(usize),
+    ) -> anyhow::Result<Transcription> {
+        if !self.skip_primary() {
+            let result = self.primary.transcribe_windows(pcm, windows, options, cancel, on_progress);
+            if !self.primary_failed(&result) {
+                return result;
+            }
+            on_progress(0);
+        }
+        self.fallback.transcribe_windows(pcm, windows, options, cancel, on_progress)
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{oneshot, watch};
+use uuid::Uuid;
+
+use crate::ai::chunking::Window;
+use crate::ai::stt::{TranscribeOptions, Transcription};
+use crate::ai::stt_backend::SttBackend;
+
+/// A clip waiting for a worker.
+struct Job {
+    id: Uuid,
+    pcm: Vec<f32>,
+    /// Windows of `pcm` to transcribe, in order.
+    windows: Vec<Window>,
+    options: TranscribeOptions,
+    cancel: Arc<AtomicBool>,
+    /// Number of finished windows.
+    progress: watch::Sender<usize>,
+    reply: oneshot::Sender<anyhow::Result<Transcription>>,
+}
+
+/// Cancel flags of queued and running jobs, with the user allowed to cancel each.
+type CancelRegistry = Mutex<HashMap<Uuid, (i64, Arc<AtomicBool>)>>;
+
+/// Runs transcriptions on dedicated OS threads so long clips never block the tokio runtime.
+/// Jobs are served FIFO from a bounded queue; when it is full, new clips are refused.
+pub struct TranscriptionPool {
+    queue: SyncSender<Job>,
+    workers: usize,
+    /// Jobs submitted but not yet picked up by a worker.
+    waiting: Arc<AtomicUsize>,
+    /// Jobs currently being transcribed.
+    running: Arc<AtomicUsize>,
+    cancels: Arc<CancelRegistry>,
+}
+
+/// A submitted transcription.
+pub struct TranscriptionJob {
+    pub id: Uuid,
+    /// Place in line when submitted; 0 means a worker picked it up right away.
+    pub position: usize,
+    /// Number of windows the clip was split into.
+    pub total: usize,
+    /// Number of windows finished so far.
+    pub progress: watch::Receiver<usize>,
+    result: oneshot::Receiver<anyhow::Result<Transcription>>,
+}
+
+impl TranscriptionJob {
+    /// Wait for the worker to finish this clip.
+    pub async fn wait(self) -> anyhow::Result<Transcription> {
+        self.result
+            .await
+            .map_err(|_| anyhow::anyhow!("Transcription worker stopped before finishing"))?
+    }
+}
+
+impl TranscriptionPool {
+    /// Start `workers` threads sharing one `backend`, with room for `queue_size` waiting clips.
+    pub fn new(backend: Arc<dyn SttBackend>, workers: usize, queue_size: usize) -> anyhow::Result<Self> {
+        let workers = workers.max(1);
+        let (queue, rx) = mpsc::sync_channel(queue_size);
+        let rx = Arc::new(Mutex::new(rx));
+        let waiting = Arc::new(AtomicUsize::new(0));
+        let running = Arc::new(AtomicUsize::new(0));
+        let cancels = Arc::new(CancelRegistry::default());
+
+        for i in 0..workers {
+            let rx = rx.clone();
+            let backend = backend.clone();
+            let waiting = waiting.clone();
+            let running = running.clone();
+            let cancels = cancels.clone();
+            std::thread::Builder::new()
+                .name(format!("stt-worker-{}", i))
+                .spawn(move || worker_loop(&rx, backend.as_ref(), &waiting, &running, &cancels))?;
+        }
+
+        tracing::info!(
+            "Transcription pool started: {} worker(s) on {}, queue of {}",
+            workers,
+            backend.name(),
+            queue_size
+        );
+        Ok(Self {
+            queue,
+            workers,
+            waiting,
+            running,
+            cancels,
+        })
+    }
+
+    /// Queue the `windows` of a clip for transcription on behalf of `owner`.
+    /// Returns `None` if the queue is full.
+    pub fn submit(
+        &self,
+        pcm: Vec<f32>,
+        windows: Vec<Window>,
+        options: TranscribeOptions,
+        owner: i64,
+    ) -> Option<TranscriptionJob> {
+        let id = Uuid::new_v4();
+        let total = windows.len();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (progress_tx, progress) = watch::channel(0);
+        let (reply, result) = oneshot::channel();
+        let job = Job {
+            id,
+            pcm,
+            windows,
+            options,
+            cancel: cancel.clone(),
+            progress: progress_tx,
+            reply,
+        };
+
+        // Count before sending so a fast worker can't decrement first
+        let ahead = self.waiting.fetch_add(1, Ordering::SeqCst);
+        let idle = self.workers.saturating_sub(self.running.load(Ordering::SeqCst));
+        self.cancels.lock().unwrap().insert(id, (owner, cancel));
+
+        match self.queue.try_send(job) {
+            Ok(()) => Some(TranscriptionJob {
+                id,
+                position: if ahead < idle { 0 } else { ahead - idle + 1 },
+                total,
+                progress,
+                result,
+            }),
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.waiting.fetch_sub(1, Ordering::SeqCst);
+                self.cancels.lock().unwrap().remove(&id);
+                None
+            }
+        }
+    }
+
+    /// Ask a queued or running job to stop. Only the user who submitted it may cancel.
+    /// Returns false if the job is unknown (already finished) or owned by someone else.
+    pub fn cancel(&self, id: Uuid, user_id: i64) -> bool {
+        match self.cancels.lock().unwrap().get(&id) {
+            Some((owner, flag)) if *owner == user_id => {
+                flag.store(true, Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn worker_loop(
+    rx: &Mutex<Receiver<Job>>,
+    backend: &dyn SttBackend,
+    waiting: &AtomicUsize,
+    running: &AtomicUsize,
+    cancels: &CancelRegistry,
+) {
+    loop {
+        // Hold the lock only while receiving, so other workers can take the next job
+        let job = match rx.lock() {
+            Ok(rx) => rx.recv(),
+            Err(_) => return,
+        };
+        let Ok(job) = job else {
+            return; // pool dropped
+        };
+
+        waiting.fetch_sub(1, Ordering::SeqCst);
+        running.fetch_add(1, Ordering::SeqCst);
+
+        // A panic inside the backend must not take the worker down with it
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            backend.transcribe_windows(
+                &job.pcm,
+                &job.windows,
+                &job.options,
+                &job.cancel,
+                &mut |done| {
+                    let _ = job.progress.send(done);
+                },
+            )
+        }))
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("Transcription worker panicked")));
+
+        running.fetch_sub(1, Ordering::SeqCst);
+        cancels.lock().unwrap().remove(&job.id);
+        let _ = job.reply.send(result);
+    }
+}
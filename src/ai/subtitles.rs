@@ -0,0 +1,85 @@
+use std::fmt::Write;
+
+use crate::ai::stt::Segment;
+
+/// How a transcript is delivered to the user.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TranscriptFormat {
+    /// Plain text message (or .txt file when long).
+    Text,
+    /// SubRip subtitles.
+    Srt,
+    /// WebVTT subtitles.
+    Vtt,
+}
+
+impl TranscriptFormat {
+    pub fn from_str_loose(s: &str) -> Self {
+        match s.trim().to_lowercase().as_str() {
+            "srt" => Self::Srt,
+            "vtt" | "webvtt" => Self::Vtt,
+            _ => Self::Text,
+        }
+    }
+
+    /// Settings value / file extension.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Text => "txt",
+            Self::Srt => "srt",
+            Self::Vtt => "vtt",
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::Text => "📄 Text",
+            Self::Srt => "🎬 SRT",
+            Self::Vtt => "🎬 VTT",
+        }
+    }
+}
+
+/// Render segments as a SubRip (.srt) file.
+pub fn to_srt(segments: &[Segment]) -> String {
+    let mut out = String::new();
+    for (i, seg) in segments.iter().filter(|s| !s.text.is_empty()).enumerate() {
+        let _ = write!(
+            out,
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            timestamp(seg.start_ms, ','),
+            timestamp(seg.end_ms, ','),
+            seg.text
+        );
+    }
+    out
+}
+
+/// Render segments as a WebVTT (.vtt) file.
+pub fn to_vtt(segments: &[Segment]) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for seg in segments.iter().filter(|s| !s.text.is_empty()) {
+        let _ = write!(
+            out,
+            "{} --> {}\n{}\n\n",
+            timestamp(seg.start_ms, '.'),
+            timestamp(seg.end_ms, '.'),
+            seg.text
+        );
+    }
+    out
+}
+
+/// `HH:MM:SS<sep>mmm` — SRT separates milliseconds with ',', VTT with '.'.
+fn timestamp(ms: i64, separator: char) -> String {
+    let ms = ms.max(0);
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
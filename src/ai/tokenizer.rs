@@ -0,0 +1,28 @@
+use tiktoken_rs::{cl100k_base_singleton, o200k_base_singleton, CoreBPE};
+
+/// Per-message overhead of the chat format (role markers, separators).
+const MESSAGE_OVERHEAD: i32 = 4;
+
+/// Pick the BPE vocabulary closest to the model's own tokenizer.
+///
+/// Llama 3 uses a tiktoken-style BPE built on cl100k, and Llama 4 / GPT-4o /
+/// gpt-oss use o200k-sized vocabularies. Other families fall back to cl100k,
+/// which is far closer for non-Latin text than a bytes/4 estimate.
+fn encoding_for_model(model: &str) -> &'static CoreBPE {
+    let model = model.to_lowercase();
+    let o200k = ["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4", "gpt-oss", "llama-4"];
+
+    if o200k.iter().any(|prefix| {
+        model.starts_with(prefix) || model.contains(&format!("/{}", prefix))
+    }) {
+        o200k_base_singleton()
+    } else {
+        cl100k_base_singleton()
+    }
+}
+
+/// Count the tokens `text` occupies as a chat message for `model`.
+pub fn count_tokens(model: &str, text: &str) -> i32 {
+    let tokens = encoding_for_model(model).encode_ordinary(text).len();
+    i32::try_from(tokens).unwrap_or(i32::MAX).saturating_add(MESSAGE_OVERHEAD)
+}
@@ -1,192 +1,240 @@
-use std::process::Stdio;
-use std::sync::atomic::{AtomicBool, Ordering};
-use tokio::process::Command;
+use async_trait::async_trait;
+use futures_util::{StreamExt, TryStreamExt};
+use std::sync::Arc;
 
+use crate::ai::tts_piper::PiperBackend;
+use crate::ai::tts_xtts::XttsBackend;
 use crate::config::AppConfig;
 
-/// Supported TTS engines
-#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum TtsEngine {
-    Piper,
-    Xtts,
+/// Encoding of the audio a backend returns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioFormat {
+    /// A complete WAV file; converted to Opus before sending as a voice note.
+    Wav,
+    /// Ogg/Opus, ready to send as a voice note.
+    OggOpus,
 }
 
-impl TtsEngine {
-    pub fn from_str_loose(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
-            "xtts" | "xtts-v2" => Self::Xtts,
-            _ => Self::Piper,
-        }
-    }
+/// A selectable voice of an engine.
+#[derive(Debug, Clone)]
+pub struct VoiceInfo {
+    /// Stable id stored in user settings (e.g. "en_US-amy-medium").
+    pub id: String,
+    /// Locale code, e.g. "en_US".
+    pub language: String,
+    /// Human-readable language, e.g. "English (United States)".
+    pub language_name: String,
+    /// Named speakers of a multi-speaker model, by speaker id; empty for single-speaker voices.
+    pub speakers: Vec<Speaker>,
+}
 
-    pub fn display_name(&self) -> &'static str {
-        match self {
-            Self::Piper => "Piper (Fast/CPU)",
-            Self::Xtts => "XTTS-v2 (Quality/GPU)",
-        }
+#[derive(Debug, Clone)]
+pub struct Speaker {
+    pub id: i64,
+    pub name: String,
+}
+
+/// What an engine can do, for picking engines and building menus.
+#[derive(Debug, Clone)]
+pub struct TtsCapabilities {
+    /// ISO 639-1 codes the engine speaks; empty means any.
+    pub languages: Vec<String>,
+    /// Selectable voices; empty means the engine has a single default voice.
+    pub voices: Vec<VoiceInfo>,
+    /// Requests the engine serves at once; long replies are spoken this many chunks at a time.
+    pub concurrency: usize,
+    pub output: AudioFormat,
+}
+
+impl TtsCapabilities {
+    pub fn speaks(&self, language: &str) -> bool {
+        self.languages.is_empty() || self.languages.iter().any(|l| l == language)
     }
 }
 
-pub struct TtsManager {
-    piper_binary_path: String,
-    piper_lib_path: String,
-    piper_model_path: String,
-    xtts_url: String,
-    /// Tracks whether XTTS sidecar is known to be available.
-    /// Reset to true on each /settings change; set to false on connection failure.
-    xtts_available: AtomicBool,
+/// How to speak one request.
+#[derive(Debug, Clone, Default)]
+pub struct SpeakOptions {
+    /// ISO 639-1 code of the text.
+    pub language: String,
+    /// Voice id the user picked with /voice; engines ignore ids they don't know.
+    pub voice: Option<String>,
+    /// Speaker of a multi-speaker voice.
+    pub speaker: Option<i64>,
 }
 
-impl TtsManager {
-    pub fn new(config: &AppConfig) -> Self {
+impl SpeakOptions {
+    /// Options for speaking `language` with the voice from the user's `settings`.
+    pub fn for_settings(settings: &serde_json::Value, language: &str) -> Self {
         Self {
-            piper_binary_path: config.piper_binary_path.clone(),
-            piper_lib_path: config.piper_lib_path.clone(),
-            piper_model_path: config.piper_model_path.clone(),
-            xtts_url: config.xtts_sidecar_url.clone(),
-            xtts_available: AtomicBool::new(true),
+            language: language.to_string(),
+            voice: settings
+                .get("tts_voice")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            speaker: settings.get("tts_speaker").and_then(|v| v.as_i64()),
         }
     }
+}
+
+/// Synthesized speech.
+pub struct Speech {
+    pub audio: Vec<u8>,
+    pub format: AudioFormat,
+    /// Id of the engine that actually spoke (may be the fallback).
+    pub engine: String,
+}
+
+/// A text-to-speech engine.
+#[async_trait]
+pub trait TtsBackend: Send + Sync {
+    /// Stable id stored in user settings (e.g. "piper").
+    fn id(&self) -> &str;
+
+    /// Name shown in /settings and /usage.
+    fn display_name(&self) -> &str;
+
+    fn capabilities(&self) -> &TtsCapabilities;
+
+    /// Speak `text` as `capabilities().output`. Engines voice languages they
+    /// don't support in their own default.
+    async fn synthesize(&self, text: &str, options: &SpeakOptions) -> anyhow::Result<Vec<u8>>;
 
-    /// Reset XTTS availability flag (e.g., when user switches to XTTS in settings).
-    pub fn reset_xtts_availability(&self) {
-        self.xtts_available.store(true, Ordering::Relaxed);
+    /// False while the engine is known to be down; it is skipped until `reset`.
+    fn is_available(&self) -> bool {
+        true
     }
 
-    /// Generate speech audio (WAV bytes) from text using the specified engine.
-    /// Falls back to Piper if XTTS is unavailable.
-    pub async fn speak(&self, text: &str, engine: &TtsEngine) -> anyhow::Result<Vec<u8>> {
-        match engine {
-            TtsEngine::Piper => self.speak_piper(text).await,
-            TtsEngine::Xtts => {
-                // Skip XTTS entirely if we already know it's down
-                if !self.xtts_available.load(Ordering::Relaxed) {
-                    tracing::debug!("XTTS known unavailable, using Piper directly");
-                    return self.speak_piper(text).await;
-                }
+    /// Forget a previous failure so the engine is tried again (e.g. when picked in /settings).
+    fn reset(&self) {}
+}
 
-                match self.speak_xtts(text).await {
-                    Ok(audio) => Ok(audio),
-                    Err(e) => {
-                        let err_str = e.to_string();
-                        // Mark XTTS as unavailable if it's a connection error
-                        if err_str.contains("not reachable") {
-                            self.xtts_available.store(false, Ordering::Relaxed);
-                            tracing::warn!(
-                                "XTTS sidecar not reachable, disabling until /settings reset. \
-                                 Falling back to Piper."
-                            );
-                        } else {
-                            tracing::warn!("XTTS failed ({}), falling back to Piper", e);
-                        }
-                        self.speak_piper(text).await
-                    }
-                }
+/// The TTS engines enabled in config, in menu order.
+pub struct TtsRegistry {
+    backends: Vec<Arc<dyn TtsBackend>>,
+    /// Used when a user has no (or an unknown) engine selected, and first in line when theirs fails.
+    default: Arc<dyn TtsBackend>,
+}
+
+impl TtsRegistry {
+    /// Register the engines listed in `TTS_ENGINES`.
+    pub fn from_config(config: &AppConfig) -> anyhow::Result<Self> {
+        let mut backends: Vec<Arc<dyn TtsBackend>> = Vec::new();
+        for id in &config.tts_engines {
+            let backend: Arc<dyn TtsBackend> = match id.as_str() {
+                "piper" => Arc::new(PiperBackend::new(config)?),
+                "xtts" => Arc::new(XttsBackend::new(config)),
+                other => anyhow::bail!("Unknown TTS engine '{}' in TTS_ENGINES (expected piper or xtts)", other),
+            };
+            if backends.iter().any(|b| b.id() == backend.id()) {
+                continue;
             }
+            backends.push(backend);
         }
-    }
 
-    /// Piper TTS: uses the standalone piper binary to generate speech.
-    async fn speak_piper(&self, text: &str) -> anyhow::Result<Vec<u8>> {
-        let child = Command::new(&self.piper_binary_path)
-            .args(["--model", &self.piper_model_path, "--output-raw"])
-            .env("LD_LIBRARY_PATH", &self.piper_lib_path)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| anyhow::anyhow!(
-                "Failed to start piper binary at '{}': {}. \
-                 Make sure the binary exists.",
-                self.piper_binary_path, e
-            ))?;
-
-        // Write text to stdin
-        let mut child = child;
-        if let Some(mut stdin) = child.stdin.take() {
-            use tokio::io::AsyncWriteExt;
-            stdin.write_all(text.as_bytes()).await?;
-            drop(stdin); // close stdin to signal EOF
-        }
+        let default = backends
+            .iter()
+            .find(|b| b.id() == config.default_tts_engine)
+            .or_else(|| backends.first())
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("TTS_ENGINES must list at least one engine"))?;
 
-        let output = child.wait_with_output().await?;
+        Ok(Self { backends, default })
+    }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            anyhow::bail!("Piper TTS failed (exit {}): {}", output.status, stderr);
-        }
+    /// All engines, in menu order.
+    pub fn backends(&self) -> &[Arc<dyn TtsBackend>] {
+        &self.backends
+    }
 
-        if output.stdout.is_empty() {
-            anyhow::bail!("Piper TTS produced no audio output");
-        }
+    pub fn get(&self, id: &str) -> Option<&Arc<dyn TtsBackend>> {
+        let id = id.to_lowercase();
+        let id = if id == "xtts-v2" { "xtts" } else { id.as_str() };
+        self.backends.iter().find(|b| b.id() == id)
+    }
 
-        // Piper with --output-raw outputs raw PCM s16le 22050Hz mono.
-        // We need to wrap it in a WAV header for Telegram.
-        let wav = pcm_to_wav(&output.stdout, 22050, 1, 16);
-        Ok(wav)
+    /// The engine offering voice `id`, and the voice.
+    pub fn find_voice(&self, id: &str) -> Option<(&Arc<dyn TtsBackend>, &VoiceInfo)> {
+        self.backends.iter().find_map(|backend| {
+            backend
+                .capabilities()
+                .voices
+                .iter()
+                .find(|v| v.id == id)
+                .map(|voice| (backend, voice))
+        })
     }
 
-    /// XTTS Sidecar: HTTP POST to the Python server.
-    /// Uses a short connection timeout (2s) so we fail fast if sidecar isn't running,
-    /// but a long response timeout (90s) to allow CPU inference.
-    async fn speak_xtts(&self, text: &str) -> anyhow::Result<Vec<u8>> {
-        let client = reqwest::Client::builder()
-            .connect_timeout(std::time::Duration::from_secs(2))
-            .timeout(std::time::Duration::from_secs(90))
-            .build()?;
-
-        let resp = client
-            .post(format!("{}/tts", self.xtts_url))
-            .json(&serde_json::json!({
-                "text": text,
-                "language": "en"
-            }))
-            .send()
-            .await
-            .map_err(|e| {
-                if e.is_connect() || e.is_timeout() {
-                    anyhow::anyhow!("XTTS sidecar not reachable at {}", self.xtts_url)
-                } else {
-                    anyhow::anyhow!("XTTS request error: {}", e)
-                }
-            })?;
+    /// The engine the user picked in /settings, or the default.
+    pub fn for_settings(&self, settings: &serde_json::Value) -> &Arc<dyn TtsBackend> {
+        settings
+            .get("tts_engine")
+            .and_then(|v| v.as_str())
+            .and_then(|id| self.get(id))
+            .unwrap_or(&self.default)
+    }
 
-        if !resp.status().is_success() {
-            let err_text = resp.text().await.unwrap_or_default();
-            anyhow::bail!("XTTS sidecar error: {}", err_text);
-        }
+    /// The engine to try when `engine` is down or fails: the default engine, else
+    /// the first other engine that isn't known to be down. Never `engine` itself.
+    fn fallback_for(&self, engine: &Arc<dyn TtsBackend>) -> Option<&Arc<dyn TtsBackend>> {
+        let others = || self.backends.iter().filter(|b| b.id() != engine.id());
+        others()
+            .find(|b| b.id() == self.default.id() && b.is_available())
+            .or_else(|| others().find(|b| b.is_available()))
+    }
 
-        let wav_bytes = resp.bytes().await?.to_vec();
-        Ok(wav_bytes)
+    /// Speak with `engine`, falling back to another engine if it is down or fails.
+    pub async fn speak(
+        &self,
+        engine: &Arc<dyn TtsBackend>,
+        text: &str,
+        options: &SpeakOptions,
+    ) -> anyhow::Result<Speech> {
+        let fallback = match self.fallback_for(engine) {
+            Some(fallback) if !engine.is_available() => {
+                tracing::debug!("{} known unavailable, using {} directly", engine.id(), fallback.id());
+                fallback
+            }
+            fallback => match engine.synthesize(text, options).await {
+                Ok(audio) => {
+                    return Ok(Speech {
+                        audio,
+                        format: engine.capabilities().output,
+                        engine: engine.id().to_string(),
+                    })
+                }
+                Err(e) => match fallback {
+                    Some(fallback) => {
+                        tracing::warn!("{} TTS failed ({}), falling back to {}", engine.id(), e, fallback.id());
+                        fallback
+                    }
+                    None => return Err(e),
+                },
+            },
+        };
+
+        let audio = fallback.synthesize(text, options).await?;
+        Ok(Speech {
+            audio,
+            format: fallback.capabilities().output,
+            engine: fallback.id().to_string(),
+        })
     }
-}
 
-/// Convert raw PCM (s16le) bytes into a proper WAV file in memory.
-fn pcm_to_wav(pcm: &[u8], sample_rate: u32, channels: u16, bits_per_sample: u16) -> Vec<u8> {
-    let data_size = pcm.len() as u32;
-    let byte_rate = sample_rate * channels as u32 * bits_per_sample as u32 / 8;
-    let block_align = channels * bits_per_sample / 8;
-    let file_size = 36 + data_size;
-
-    let mut wav = Vec::with_capacity(44 + pcm.len());
-    // RIFF header
-    wav.extend_from_slice(b"RIFF");
-    wav.extend_from_slice(&file_size.to_le_bytes());
-    wav.extend_from_slice(b"WAVE");
-    // fmt chunk
-    wav.extend_from_slice(b"fmt ");
-    wav.extend_from_slice(&16u32.to_le_bytes()); // chunk size
-    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM format
-    wav.extend_from_slice(&channels.to_le_bytes());
-    wav.extend_from_slice(&sample_rate.to_le_bytes());
-    wav.extend_from_slice(&byte_rate.to_le_bytes());
-    wav.extend_from_slice(&block_align.to_le_bytes());
-    wav.extend_from_slice(&bits_per_sample.to_le_bytes());
-    // data chunk
-    wav.extend_from_slice(b"data");
-    wav.extend_from_slice(&data_size.to_le_bytes());
-    wav.extend_from_slice(pcm);
-
-    wav
+    /// Speak `chunks` in order, up to `engine`'s concurrency at a time. Each chunk
+    /// falls back to another engine on its own, as in `speak`.
+    pub async fn speak_chunks(
+        &self,
+        engine: &Arc<dyn TtsBackend>,
+        chunks: &[String],
+        options: &SpeakOptions,
+    ) -> anyhow::Result<Vec<Speech>> {
+        // Built up front: a mapping closure inside the stream trips a higher-ranked
+        // lifetime error once this future is awaited from the (Send) message handler
+        let requests: Vec<_> = chunks.iter().map(|chunk| self.speak(engine, chunk, options)).collect();
+        futures_util::stream::iter(requests)
+            .buffered(engine.capabilities().concurrency.max(1))
+            .try_collect()
+            .await
+    }
 }
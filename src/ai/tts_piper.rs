@@ -0,0 +1,160 @@
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::ai::piper_pool::PiperPool;
+use crate::ai::piper_voice::PiperVoice;
+use crate::ai::tts::{AudioFormat, SpeakOptions, TtsBackend, TtsCapabilities};
+use crate::config::AppConfig;
+
+/// Piper: the standalone piper binary, fast on CPU.
+pub struct PiperBackend {
+    pool: Arc<PiperPool>,
+    voices: Vec<PiperVoice>,
+    /// Index of the `PIPER_MODEL_PATH` voice, used when nothing better fits.
+    default_voice: usize,
+    capabilities: TtsCapabilities,
+}
+
+impl PiperBackend {
+    /// Scan `PIPER_VOICES_DIR` for voices; `PIPER_MODEL_PATH` is the default voice.
+    /// Invalid voices are skipped with an error in the log; fails if the default
+    /// voice is invalid or no usable voice is left.
+    pub fn new(config: &AppConfig) -> anyhow::Result<Self> {
+        let mut paths = voice_files(Path::new(&config.piper_voices_dir));
+        let default_path = PathBuf::from(&config.piper_model_path);
+        if default_path.exists() && !paths.iter().any(|p| same_file(p, &default_path)) {
+            paths.push(default_path.clone());
+        }
+
+        let mut voices: Vec<PiperVoice> = Vec::new();
+        for path in paths {
+            match PiperVoice::load(&path) {
+                Ok(voice) if voices.iter().any(|v| v.info.id == voice.info.id) => {
+                    tracing::warn!("Duplicate Piper voice {}, ignoring {}", voice.info.id, path.display());
+                }
+                Ok(voice) => {
+                    tracing::debug!(
+                        "Piper voice {} ({}, {} Hz, {} speakers)",
+                        voice.info.id,
+                        voice.info.language,
+                        voice.sample_rate(),
+                        voice.config.num_speakers
+                    );
+                    voices.push(voice);
+                }
+                Err(e) if same_file(&path, &default_path) => {
+                    anyhow::bail!("Default Piper voice {} is invalid: {}", path.display(), e);
+                }
+                Err(e) => tracing::error!("Skipping Piper voice {}: {}", path.display(), e),
+            }
+        }
+        voices.sort_by(|a, b| a.info.language.cmp(&b.info.language).then(a.info.id.cmp(&b.info.id)));
+
+        if voices.is_empty() {
+            anyhow::bail!(
+                "No usable Piper voices in {} or at {} (each needs <voice>.onnx and <voice>.onnx.json)",
+                config.piper_voices_dir,
+                config.piper_model_path
+            );
+        }
+        let default_voice = voices
+            .iter()
+            .position(|v| same_file(&v.model_path, &default_path))
+            .unwrap_or_else(|| {
+                tracing::warn!(
+                    "PIPER_MODEL_PATH {} not found, defaulting to {}",
+                    config.piper_model_path,
+                    voices[0].info.id
+                );
+                0
+            });
+
+        let mut languages: Vec<String> = voices.iter().map(PiperVoice::iso_language).collect();
+        languages.sort();
+        languages.dedup();
+
+        // Load the default voice now rather than on the first reply
+        let pool = PiperPool::new(config);
+        let (warm_pool, warm_voice) = (pool.clone(), voices[default_voice].clone());
+        tokio::spawn(async move {
+            if let Err(e) = warm_pool.warm_up(&warm_voice).await {
+                tracing::error!("Could not start Piper worker for {}: {}", warm_voice.info.id, e);
+            }
+        });
+
+        Ok(Self {
+            pool,
+            capabilities: TtsCapabilities {
+                languages,
+                voices: voices.iter().map(|v| v.info.clone()).collect(),
+                concurrency: config.piper_processes_per_voice.max(1),
+                output: AudioFormat::Wav,
+            },
+            voices,
+            default_voice,
+        })
+    }
+
+    /// The user's voice if it is installed; otherwise the default voice if it
+    /// speaks the text's language, else any voice that does, else the default.
+    fn pick_voice(&self, options: &SpeakOptions) -> &PiperVoice {
+        if let Some(voice) = options
+            .voice
+            .as_deref()
+            .and_then(|id| self.voices.iter().find(|v| v.info.id == id))
+        {
+            return voice;
+        }
+
+        let default = &self.voices[self.default_voice];
+        Some(default)
+            .filter(|v| v.iso_language() == options.language)
+            .or_else(|| self.voices.iter().find(|v| v.iso_language() == options.language))
+            .unwrap_or(default)
+    }
+}
+
+#[async_trait]
+impl TtsBackend for PiperBackend {
+    fn id(&self) -> &str {
+        "piper"
+    }
+
+    fn display_name(&self) -> &str {
+        "Piper (Fast/CPU)"
+    }
+
+    fn capabilities(&self) -> &TtsCapabilities {
+        &self.capabilities
+    }
+
+    async fn synthesize(&self, text: &str, options: &SpeakOptions) -> anyhow::Result<Vec<u8>> {
+        let voice = self.pick_voice(options);
+        // The speaker only applies to the voice it was picked for
+        let speaker = options
+            .speaker
+            .filter(|_| options.voice.as_deref() == Some(voice.info.id.as_str()))
+            .filter(|id| voice.info.speakers.iter().any(|s| s.id == *id));
+
+        self.pool.synthesize(voice, speaker, text).await
+    }
+}
+
+/// `.onnx` files directly inside `dir`.
+fn voice_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "onnx"))
+        .collect()
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
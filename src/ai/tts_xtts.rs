@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::ai::tts::{AudioFormat, SpeakOptions, TtsBackend, TtsCapabilities};
+use crate::config::AppConfig;
+
+/// Languages the XTTS-v2 model can speak; anything else is voiced in English.
+const XTTS_LANGUAGES: &[&str] = &[
+    "en", "es", "fr", "de", "it", "pt", "pl", "tr", "ru", "nl", "cs", "ar", "zh", "ja", "hu",
+    "ko", "hi",
+];
+
+/// XTTS-v2 via the Python sidecar: better quality, wants a GPU.
+pub struct XttsBackend {
+    url: String,
+    capabilities: TtsCapabilities,
+    /// Tracks whether XTTS sidecar is known to be available.
+    /// Reset to true on each /settings change; set to false on connection failure.
+    available: AtomicBool,
+}
+
+impl XttsBackend {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            url: config.xtts_sidecar_url.clone(),
+            capabilities: TtsCapabilities {
+                languages: XTTS_LANGUAGES.iter().map(|l| l.to_string()).collect(),
+                voices: Vec::new(),
+                // The sidecar runs one model and handles requests one at a time
+                concurrency: 1,
+                output: AudioFormat::Wav,
+            },
+            available: AtomicBool::new(true),
+        }
+    }
+}
+
+#[async_trait]
+impl TtsBackend for XttsBackend {
+    fn id(&self) -> &str {
+        "xtts"
+    }
+
+    fn display_name(&self) -> &str {
+        "XTTS-v2 (Quality/GPU)"
+    }
+
+    fn capabilities(&self) -> &TtsCapabilities {
+        &self.capabilities
+    }
+
+    /// HTTP POST to the Python server.
+    /// Uses a short connection timeout (2s) so we fail fast if sidecar isn't running,
+    /// but a long response timeout (90s) to allow CPU inference.
+    async fn synthesize(&self, text: &str, options: &SpeakOptions) -> anyhow::Result<Vec<u8>> {
+        let language = match options.language.as_str() {
+            "zh" => "zh-cn",
+            l if self.capabilities.speaks(l) => l,
+            _ => "en",
+        };
+
+        let client = reqwest::Client::builder()
+            .connect_timeout(std::time::Duration::from_secs(2))
+            .timeout(std::time::Duration::from_secs(90))
+            .build()?;
+
+        let resp = client
+            .post(format!("{}/tts", self.url))
+            .json(&serde_json::json!({
+                "text": text,
+                "language": language
+            }))
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_connect() || e.is_timeout() {
+                    // Skip XTTS until the user picks it again in /settings
+                    self.available.store(false, Ordering::Relaxed);
+                    tracing::warn!("XTTS sidecar not reachable, disabling until /settings reset.");
+                    anyhow::anyhow!("XTTS sidecar not reachable at {}", self.url)
+                } else {
+                    anyhow::anyhow!("XTTS request error: {}", e)
+                }
+            })?;
+
+        if !resp.status().is_success() {
+            let err_text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("XTTS sidecar error: {}", err_text);
+        }
+
+        let wav_bytes = resp.bytes().await?.to_vec();
+        Ok(wav_bytes)
+    }
+
+    fn is_available(&self) -> bool {
+        self.available.load(Ordering::Relaxed)
+    }
+
+    fn reset(&self) {
+        self.available.store(true, Ordering::Relaxed);
+    }
+}
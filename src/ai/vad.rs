@@ -0,0 +1,166 @@
+use std::fmt;
+use std::ops::Range;
+
+/// Sample rate of the PCM fed to whisper.
+const SAMPLE_RATE: usize = 16_000;
+/// Analysis frame: 30 ms.
+const FRAME: usize = SAMPLE_RATE * 30 / 1000;
+/// Pauses shorter than this are treated as part of the surrounding speech.
+const MIN_SILENCE_FRAMES: usize = 10; // 300 ms
+/// Bursts shorter than this are clicks, not speech.
+const MIN_SPEECH_FRAMES: usize = 5; // 150 ms
+/// Audio kept around detected speech so word edges aren't clipped.
+const PADDING: usize = SAMPLE_RATE / 5; // 200 ms
+/// Clips shorter than this are rejected outright.
+const MIN_CLIP: usize = SAMPLE_RATE / 2; // 0.5 s
+/// Total speech below this is not worth transcribing.
+const MIN_TOTAL_SPEECH: usize = SAMPLE_RATE * 3 / 10; // 0.3 s
+/// Speech threshold never sits more than this far below the loudest frame.
+const PEAK_HEADROOM_DB: f32 = 20.0;
+/// Speech threshold sits at least this far above the noise floor.
+const FLOOR_MARGIN_DB: f32 = 8.0;
+/// Loudness spread below which a clip is steady noise or silence, not speech.
+const MIN_DYNAMIC_RANGE_DB: f32 = 6.0;
+
+/// Why a clip was not sent to whisper.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VadRejection {
+    /// Shorter than half a second.
+    TooShort,
+    /// Nothing that looks like speech (silence or steady background noise).
+    NoSpeech,
+}
+
+impl fmt::Display for VadRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort => write!(f, "recording is too short to contain speech"),
+            Self::NoSpeech => write!(f, "no speech detected"),
+        }
+    }
+}
+
+impl std::error::Error for VadRejection {}
+
+/// Energy-based voice activity detection settings.
+#[derive(Debug, Clone)]
+pub struct VadConfig {
+    /// Frames quieter than this (dBFS) are never speech.
+    pub threshold_db: f32,
+    /// Longest chunk handed to whisper (at least 1 s); longer speech is split at pauses.
+    pub max_chunk_secs: u32,
+}
+
+/// Find the speech in 16 kHz mono PCM.
+/// Returns sample ranges to transcribe: leading/trailing silence trimmed and
+/// long audio split at the longest pauses near every `max_chunk_secs`.
+pub fn detect(pcm: &[f32], config: &VadConfig) -> Result<Vec<Range<usize>>, VadRejection> {
+    if pcm.len() < MIN_CLIP {
+        return Err(VadRejection::TooShort);
+    }
+
+    let levels: Vec<f32> = pcm.chunks(FRAME).map(frame_db).collect();
+
+    let mut sorted = levels.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let floor = percentile(&sorted, 0.10);
+    let loud = percentile(&sorted, 0.95);
+    let peak = sorted[sorted.len() - 1];
+
+    if loud - floor < MIN_DYNAMIC_RANGE_DB {
+        return Err(VadRejection::NoSpeech);
+    }
+
+    let threshold = (floor + FLOOR_MARGIN_DB)
+        .min(peak - PEAK_HEADROOM_DB)
+        .max(config.threshold_db);
+
+    let runs = speech_runs(&levels, threshold);
+    let speech_frames: usize = runs.iter().map(|r| r.len()).sum();
+    if runs.is_empty() || speech_frames * FRAME < MIN_TOTAL_SPEECH {
+        return Err(VadRejection::NoSpeech);
+    }
+
+    // Runs in samples, with padding, clamped to the clip
+    let runs: Vec<Range<usize>> = runs
+        .into_iter()
+        .map(|r| (r.start * FRAME).saturating_sub(PADDING)..(r.end * FRAME + PADDING).min(pcm.len()))
+        .collect();
+
+    // At least a second, so a zero setting can't cut at every pause
+    Ok(split_at_pauses(&runs, config.max_chunk_secs.max(1) as usize * SAMPLE_RATE))
+}
+
+/// RMS level of a frame in dBFS.
+fn frame_db(frame: &[f32]) -> f32 {
+    let mean_sq = frame.iter().map(|s| s * s).sum::<f32>() / frame.len().max(1) as f32;
+    10.0 * (mean_sq + 1e-12).log10()
+}
+
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    sorted[((sorted.len() - 1) as f32 * p) as usize]
+}
+
+/// Frame ranges above `threshold`, with short pauses bridged and short bursts dropped.
+fn speech_runs(levels: &[f32], threshold: f32) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    let mut start = None;
+
+    for (i, &level) in levels.iter().enumerate() {
+        match (level > threshold, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                runs.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        runs.push(s..levels.len());
+    }
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(runs.len());
+    for run in runs {
+        match merged.last_mut() {
+            Some(prev) if run.start - prev.end < MIN_SILENCE_FRAMES => prev.end = run.end,
+            _ => merged.push(run),
+        }
+    }
+
+    merged.retain(|r| r.len() >= MIN_SPEECH_FRAMES);
+    merged
+}
+
+/// Cover the speech runs with chunks of about `max_len` samples, cutting in the
+/// middle of the longest pause available. Speech with no pause in reach runs on to
+/// the next pause; such chunks are split into overlapping windows later.
+fn split_at_pauses(runs: &[Range<usize>], max_len: usize) -> Vec<Range<usize>> {
+    let end = runs[runs.len() - 1].end;
+    let mut chunks = Vec::new();
+    let mut cursor = runs[0].start;
+
+    while end - cursor > max_len {
+        let limit = cursor + max_len;
+
+        // Pauses between runs whose midpoint falls in the back half of this chunk
+        let pauses = runs
+            .windows(2)
+            .map(|w| w[0].end..w[1].start)
+            .filter(|gap| gap.start < gap.end)
+            .map(|gap| (gap.len(), gap.start + gap.len() / 2));
+        let cut = pauses
+            .clone()
+            .filter(|&(_, mid)| mid > cursor + max_len / 2 && mid <= limit)
+            .max_by_key(|&(len, _)| len)
+            .or_else(|| pauses.clone().find(|&(_, mid)| mid > limit))
+            .map(|(_, mid)| mid)
+            .unwrap_or(end);
+
+        chunks.push(cursor..cut);
+        cursor = cut;
+    }
+
+    chunks.push(cursor..end);
+    chunks
+}
@@ -0,0 +1,302 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use whisper_rs::{WhisperContext, WhisperContextParameters};
+
+use crate::config::AppConfig;
+
+/// Speed/accuracy trade-off of a whisper model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelTier {
+    Tiny,
+    Base,
+    Small,
+    Medium,
+    Large,
+}
+
+impl ModelTier {
+    /// From fastest to most accurate.
+    pub const ALL: [ModelTier; 5] = [Self::Tiny, Self::Base, Self::Small, Self::Medium, Self::Large];
+
+    pub fn from_str_loose(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "tiny" => Some(Self::Tiny),
+            "base" => Some(Self::Base),
+            "small" => Some(Self::Small),
+            "medium" => Some(Self::Medium),
+            "large" => Some(Self::Large),
+            _ => None,
+        }
+    }
+
+    /// Settings value, as used in ggml model file names.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tiny => "tiny",
+            Self::Base => "base",
+            Self::Small => "small",
+            Self::Medium => "medium",
+            Self::Large => "large",
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::Tiny => "⚡ Tiny",
+            Self::Base => "🏃 Base",
+            Self::Small => "🎯 Small",
+            Self::Medium => "🧠 Medium",
+            Self::Large => "🐘 Large",
+        }
+    }
+}
+
+/// One model: a tier in its English-only (`ggml-<tier>.en*.bin`) or
+/// multilingual (`ggml-<tier>*.bin`) flavour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelKey {
+    pub tier: ModelTier,
+    pub english_only: bool,
+}
+
+impl ModelKey {
+    /// Parse whisper.cpp model names: `ggml-base.bin`, `ggml-small.en-q5_1.bin`,
+    /// `ggml-large-v3-turbo.bin`, ...
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let rest = name.strip_prefix("ggml-")?.strip_suffix(".bin")?;
+        let tier_len = rest.find(['.', '-']).unwrap_or(rest.len());
+        Some(Self {
+            tier: ModelTier::from_str_loose(&rest[..tier_len])?,
+            english_only: rest[tier_len..].starts_with(".en"),
+        })
+    }
+}
+
+/// Quantised and tinydiarize builds are only used when the plain model is missing.
+fn is_variant(name: &str) -> bool {
+    name.contains("-q") || name.contains("-tdrz")
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// A loaded model, shared with the transcriptions using it.
+#[derive(Clone)]
+pub struct LoadedModel {
+    pub key: ModelKey,
+    pub ctx: Arc<WhisperContext>,
+    /// Whether the model can detect and transcribe languages other than English.
+    pub multilingual: bool,
+}
+
+struct Entry {
+    model: LoadedModel,
+    bytes: u64,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: Vec<Entry>,
+    /// Bumped on every lookup; orders entries for LRU eviction.
+    clock: u64,
+}
+
+/// The whisper models found in `WHISPER_MODELS_DIR` (plus `WHISPER_MODEL_PATH`).
+/// Models are loaded on first use and the least recently used ones are dropped
+/// when the loaded set outgrows the memory budget. A dropped model stays alive
+/// until its running jobs finish.
+pub struct ModelRegistry {
+    /// The file used for each available model.
+    files: Vec<(ModelKey, PathBuf)>,
+    budget_bytes: u64,
+    cache: Mutex<Cache>,
+}
+
+impl ModelRegistry {
+    /// Scan the models directory. `WHISPER_MODEL_PATH`, if set, must exist and
+    /// wins over other files of its tier; a name that doesn't follow whisper.cpp's
+    /// naming is used as the default tier.
+    pub fn new(config: &AppConfig) -> anyhow::Result<Self> {
+        let mut candidates: Vec<(ModelKey, PathBuf)> = std::fs::read_dir(&config.whisper_models_dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file())
+            .filter_map(|p| ModelKey::from_file_name(&file_name(&p)).map(|key| (key, p)))
+            .collect();
+        candidates.sort_by_key(|(_, p)| {
+            let name = file_name(p);
+            // Plain builds first, then the newest name ("large-v3" over "large-v2")
+            (is_variant(&name), std::cmp::Reverse(name))
+        });
+
+        let mut files: Vec<(ModelKey, PathBuf)> = Vec::new();
+        if let Some(pinned) = config.whisper_model_path.as_deref().map(PathBuf::from) {
+            if !pinned.is_file() {
+                anyhow::bail!("Whisper model not found at WHISPER_MODEL_PATH '{}'", pinned.display());
+            }
+            let key = ModelKey::from_file_name(&file_name(&pinned)).unwrap_or(ModelKey {
+                tier: ModelTier::from_str_loose(&config.stt_model_tier).unwrap_or(ModelTier::Base),
+                english_only: false,
+            });
+            files.push((key, pinned));
+        }
+        for (key, path) in candidates {
+            if !files.iter().any(|(k, _)| *k == key) {
+                files.push((key, path));
+            }
+        }
+
+        if files.is_empty() {
+            anyhow::bail!(
+                "No whisper models found in '{}'. Download e.g. ggml-base.bin from: \
+                 https://huggingface.co/ggerganov/whisper.cpp/tree/main",
+                config.whisper_models_dir
+            );
+        }
+        let found: Vec<String> = files.iter().map(|(_, p)| file_name(p)).collect();
+        tracing::info!("Whisper models available: {}", found.join(", "));
+        if files.iter().all(|(key, _)| key.english_only) {
+            tracing::warn!("Only English-only whisper models installed; all speech will be transcribed as English");
+        }
+
+        Ok(Self {
+            files,
+            budget_bytes: config.stt_models_max_mb * 1024 * 1024,
+            cache: Mutex::new(Cache::default()),
+        })
+    }
+
+    fn path(&self, key: &ModelKey) -> Option<&Path> {
+        self.files.iter().find(|(k, _)| k == key).map(|(_, p)| p.as_path())
+    }
+
+    fn exists(&self, key: &ModelKey) -> bool {
+        self.path(key).is_some()
+    }
+
+    /// Tiers with at least one model file on disk.
+    pub fn available_tiers(&self) -> Vec<ModelTier> {
+        ModelTier::ALL
+            .into_iter()
+            .filter(|&tier| {
+                self.exists(&ModelKey { tier, english_only: false })
+                    || self.exists(&ModelKey { tier, english_only: true })
+            })
+            .collect()
+    }
+
+    /// The best installed model for `tier` and a whisper `language` code.
+    /// English gets the English-only model when there is one; other languages
+    /// (and "auto") get a multilingual model, or an English-only one if that's
+    /// all there is. If the tier is missing, the nearest tier is used,
+    /// preferring the faster one on a tie.
+    pub fn resolve(&self, tier: ModelTier, language: &str) -> Option<ModelKey> {
+        let rank = |t: ModelTier| ModelTier::ALL.iter().position(|&x| x == t).unwrap_or(0);
+        let wanted = rank(tier);
+
+        let mut tiers = ModelTier::ALL.to_vec();
+        tiers.sort_by_key(|&t| (rank(t).abs_diff(wanted), rank(t)));
+
+        let nearest = |english_only: bool| {
+            tiers
+                .iter()
+                .map(|&tier| ModelKey { tier, english_only })
+                .find(|key| self.exists(key))
+        };
+        if language == "en" {
+            tiers.iter().find_map(|&tier| {
+                [true, false]
+                    .map(|english_only| ModelKey { tier, english_only })
+                    .into_iter()
+                    .find(|key| self.exists(key))
+            })
+        } else {
+            nearest(false).or_else(|| nearest(true))
+        }
+    }
+
+    /// Get a model, loading it if needed. Loading happens outside the cache lock
+    /// so other workers keep using already-loaded models meanwhile.
+    pub fn get(&self, key: ModelKey) -> anyhow::Result<LoadedModel> {
+        {
+            let mut cache = self.cache.lock().unwrap();
+            cache.clock += 1;
+            let now = cache.clock;
+            if let Some(entry) = cache.entries.iter_mut().find(|e| e.model.key == key) {
+                entry.last_used = now;
+                return Ok(entry.model.clone());
+            }
+        }
+
+        let path = self
+            .path(&key)
+            .ok_or_else(|| anyhow::anyhow!("No whisper model installed for {:?}", key))?
+            .to_path_buf();
+        let bytes = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        let started = std::time::Instant::now();
+        let ctx = WhisperContext::new_with_params(
+            &path.to_string_lossy(),
+            WhisperContextParameters::default(),
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to load whisper model '{}': {}", path.display(), e))?;
+
+        let multilingual = ctx.is_multilingual();
+        if !multilingual && !key.english_only {
+            tracing::warn!(
+                "Whisper model '{}' is English-only; language settings will be ignored",
+                path.display()
+            );
+        }
+        tracing::info!(
+            "Loaded whisper model '{}' ({} MB) in {:.1}s",
+            file_name(&path),
+            bytes / (1024 * 1024),
+            started.elapsed().as_secs_f32()
+        );
+
+        let model = LoadedModel {
+            key,
+            ctx: Arc::new(ctx),
+            multilingual,
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        cache.clock += 1;
+        let now = cache.clock;
+        // Another worker may have loaded the same model meanwhile
+        if let Some(entry) = cache.entries.iter_mut().find(|e| e.model.key == key) {
+            entry.last_used = now;
+            return Ok(entry.model.clone());
+        }
+        cache.entries.push(Entry {
+            model: model.clone(),
+            bytes,
+            last_used: now,
+        });
+        self.evict(&mut cache, key);
+
+        Ok(model)
+    }
+
+    /// Drop least recently used models until the rest fit the budget, always
+    /// keeping the one just requested.
+    fn evict(&self, cache: &mut Cache, keep: ModelKey) {
+        while cache.entries.iter().map(|e| e.bytes).sum::<u64>() > self.budget_bytes {
+            let Some(oldest) = cache
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| e.model.key != keep)
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(i, _)| i)
+            else {
+                break;
+            };
+            let entry = cache.entries.remove(oldest);
+            tracing::info!("Unloaded whisper model {:?}", entry.model.key);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+use std::io::Cursor;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::audio::opus;
+
+/// The file has no audio to decode: a silent video, or an empty stream.
+/// Every other decoding error means the file or a decoder is broken.
+#[derive(Debug)]
+pub struct NoAudioTrack;
+
+impl fmt::Display for NoAudioTrack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no audio track")
+    }
+}
+
+impl std::error::Error for NoAudioTrack {}
+
+/// Mono PCM at the source's own sample rate.
+pub struct DecodedAudio {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+/// Decode a whole file to mono f32, downmixing multi-channel audio.
+/// Ogg/Opus goes to libopus; everything else to symphonia.
+pub fn decode(data: &[u8]) -> anyhow::Result<DecodedAudio> {
+    if opus::is_ogg_opus(data) {
+        return opus::decode_ogg(data);
+    }
+    decode_symphonia(data)
+}
+
+fn decode_symphonia(data: &[u8]) -> anyhow::Result<DecodedAudio> {
+    let source = MediaSourceStream::new(Box::new(Cursor::new(data.to_vec())), Default::default());
+    let probed = symphonia::default::get_probe().format(
+        &Hint::new(),
+        source,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+
+    // Videos carry other tracks too; take the first one with audio
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL && t.codec_params.sample_rate.is_some())
+        .ok_or(NoAudioTrack)?;
+    let track_id = track.id;
+    let mut sample_rate = track.codec_params.sample_rate.unwrap_or(0);
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut samples = Vec::new();
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet costs a few milliseconds of audio, not the whole file
+            Err(SymphoniaError::DecodeError(e)) => {
+                tracing::debug!("Skipping undecodable packet: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let spec = *decoded.spec();
+        sample_rate = spec.rate;
+        let buf = match &mut buffer {
+            Some(buf) if buf.capacity() >= decoded.capacity() * spec.channels.count() => buf,
+            _ => buffer.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+        };
+        buf.copy_interleaved_ref(decoded);
+        downmix_into(&mut samples, buf.samples(), spec.channels.count());
+    }
+
+    if samples.is_empty() || sample_rate == 0 {
+        return Err(NoAudioTrack.into());
+    }
+    Ok(DecodedAudio {
+        samples,
+        sample_rate,
+    })
+}
+
+/// Append interleaved `frames` with `channels` channels to `out` as mono.
+pub fn downmix_into(out: &mut Vec<f32>, frames: &[f32], channels: usize) {
+    if channels <= 1 {
+        out.extend_from_slice(frames);
+        return;
+    }
+    out.extend(
+        frames
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+    );
+}
@@ -0,0 +1,38 @@
+//! Noise suppression for speech recorded in noisy places (cars, streets), using
+//! RNNoise through its pure-Rust port, nnnoiseless.
+
+use nnnoiseless::DenoiseState;
+
+use crate::audio::resample;
+
+/// The only rate RNNoise works at.
+pub const SAMPLE_RATE: u32 = 48_000;
+/// RNNoise takes samples on the 16-bit integer scale.
+const SCALE: f32 = 32_768.0;
+
+/// Suppress background noise in mono PCM at `sample_rate`; the result is at
+/// [`SAMPLE_RATE`]. Some of the original is mixed back in so that nothing is
+/// attenuated by more than `max_attenuation_db`, which keeps speech natural.
+pub fn denoise(pcm: &[f32], sample_rate: u32, max_attenuation_db: f32) -> anyhow::Result<Vec<f32>> {
+    let input = resample::resample(pcm, sample_rate, SAMPLE_RATE)?;
+    let frame = DenoiseState::FRAME_SIZE;
+    let floor = 10f32.powf(-max_attenuation_db.max(0.0) / 20.0);
+
+    // The output lags the input by one frame; an extra frame of silence flushes the tail
+    let mut scaled: Vec<f32> = input.iter().map(|s| s * SCALE).collect();
+    scaled.resize(input.len().next_multiple_of(frame) + frame, 0.0);
+
+    let mut state = DenoiseState::new();
+    let mut denoised = Vec::with_capacity(scaled.len());
+    let mut out = [0.0f32; DenoiseState::FRAME_SIZE];
+    for chunk in scaled.chunks_exact(frame) {
+        state.process_frame(&mut out, chunk);
+        denoised.extend(out.iter().map(|s| s / SCALE));
+    }
+
+    Ok(denoised[frame..frame + input.len()]
+        .iter()
+        .zip(&input)
+        .map(|(clean, original)| clean * (1.0 - floor) + original * floor)
+        .collect())
+}
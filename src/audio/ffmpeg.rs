@@ -0,0 +1,99 @@
+//! ffmpeg subprocess fallback for formats the native decoders don't handle.
+
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::audio::decode::NoAudioTrack;
+use crate::audio::TempFile;
+
+/// Extract the audio track of any container ffmpeg understands as PCM f32 16kHz mono.
+/// The input goes through a temp file because formats like MP4 need a seekable input.
+pub async fn decode_to_pcm(data: &[u8]) -> anyhow::Result<Vec<f32>> {
+    let input = TempFile::new();
+    tokio::fs::write(&input.0, data).await?;
+
+    let output = Command::new("ffmpeg")
+        .arg("-i")
+        .arg(&input.0)
+        .args([
+            "-vn",
+            "-f", "f32le",
+            "-acodec", "pcm_f32le",
+            "-ar", "16000",
+            "-ac", "1",
+            "pipe:1",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        // With video dropped (-vn), a file without audio leaves nothing to write
+        if String::from_utf8_lossy(&output.stderr).contains("does not contain any stream") {
+            return Err(NoAudioTrack.into());
+        }
+        anyhow::bail!(
+            "ffmpeg audio extraction failed ({}): {}",
+            output.status,
+            last_line(&output.stderr)
+        );
+    }
+
+    // Convert raw bytes to f32 samples
+    let samples: Vec<f32> = output
+        .stdout
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect();
+
+    if samples.is_empty() {
+        return Err(NoAudioTrack.into());
+    }
+    Ok(samples)
+}
+
+/// Convert WAV to OGG/Opus for Telegram voice messages.
+pub async fn wav_to_ogg(wav_data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut child = Command::new("ffmpeg")
+        .args([
+            "-i", "pipe:0",
+            "-acodec", "libopus",
+            "-f", "ogg",
+            "pipe:1",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(wav_data).await?;
+        drop(stdin);
+    }
+
+    let output = child.wait_with_output().await?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "ffmpeg wav-to-ogg conversion failed ({}): {}",
+            output.status,
+            last_line(&output.stderr)
+        );
+    }
+
+    Ok(output.stdout)
+}
+
+/// The last non-empty line of ffmpeg's stderr, which carries the actual error.
+fn last_line(stderr: &[u8]) -> String {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
@@ -0,0 +1,26 @@
+use crate::audio::decode::DecodedAudio;
+use crate::audio::resample;
+
+/// Concatenate `clips` at the first clip's sample rate, grouped into parts of at
+/// most `max_secs` seconds. Parts only break between clips, so a sentence is never
+/// cut; a clip longer than the limit is a part of its own.
+pub fn join(clips: Vec<DecodedAudio>, max_secs: u32) -> anyhow::Result<Vec<DecodedAudio>> {
+    let Some(sample_rate) = clips.first().map(|c| c.sample_rate) else {
+        return Ok(Vec::new());
+    };
+    let max_len = max_secs.max(1) as usize * sample_rate as usize;
+
+    let mut parts: Vec<DecodedAudio> = Vec::new();
+    for clip in clips {
+        let samples = if clip.sample_rate == sample_rate {
+            clip.samples
+        } else {
+            resample::resample(&clip.samples, clip.sample_rate, sample_rate)?
+        };
+        match parts.last_mut() {
+            Some(part) if part.samples.len() + samples.len() <= max_len => part.samples.extend(samples),
+            _ => parts.push(DecodedAudio { samples, sample_rate }),
+        }
+    }
+    Ok(parts)
+}
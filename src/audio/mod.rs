@@ -0,0 +1,140 @@
+//! In-process audio decoding, resampling and voice-note encoding.
+//! ffmpeg is only used as a fallback for formats the native decoders can't read.
+
+pub mod decode;
+pub mod denoise;
+pub mod ffmpeg;
+pub mod join;
+pub mod opus;
+pub mod resample;
+pub mod wav;
+
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Sample rate of the PCM whisper expects.
+pub const WHISPER_SAMPLE_RATE: u32 = 16_000;
+
+/// Decode any supported audio or video file to mono f32 PCM at its own sample
+/// rate (16 kHz when it went through ffmpeg).
+/// Ogg/Opus, MP3, WAV, FLAC, Vorbis and AAC/ALAC (MP4/M4A) are decoded natively;
+/// anything else goes through ffmpeg when `ffmpeg_fallback` is set.
+pub async fn decode_audio(data: Vec<u8>, ffmpeg_fallback: bool) -> anyhow::Result<decode::DecodedAudio> {
+    let (data, native) = tokio::task::spawn_blocking(move || {
+        let result = decode::decode(&data);
+        (data, result)
+    })
+    .await?;
+
+    match native {
+        Ok(audio) => Ok(audio),
+        // ffmpeg won't find audio the native decoders saw isn't there
+        Err(e) if e.is::<decode::NoAudioTrack>() => Err(e),
+        Err(e) if ffmpeg_fallback => {
+            tracing::debug!("Native decoding failed ({:#}), trying ffmpeg", e);
+            Ok(decode::DecodedAudio {
+                samples: ffmpeg::decode_to_pcm(&data).await?,
+                sample_rate: WHISPER_SAMPLE_RATE,
+            })
+        }
+        Err(e) => Err(e.context("Unsupported audio format")),
+    }
+}
+
+/// Encode a WAV file (as produced by TTS) as an Ogg/Opus Telegram voice note.
+pub async fn wav_to_voice(wav: Vec<u8>, ffmpeg_fallback: bool) -> anyhow::Result<Vec<u8>> {
+    let (wav, native) = tokio::task::spawn_blocking(move || {
+        let result = decode::decode(&wav).and_then(|audio| {
+            let pcm = resample::resample(&audio.samples, audio.sample_rate, opus::SAMPLE_RATE)?;
+            opus::encode_ogg(&pcm, audio.sample_rate)
+        });
+        (wav, result)
+    })
+    .await?;
+
+    match native {
+        Ok(ogg) => Ok(ogg),
+        Err(e) if ffmpeg_fallback => {
+            tracing::warn!("Native Opus encoding failed ({:#}), trying ffmpeg", e);
+            ffmpeg::wav_to_ogg(&wav).await
+        }
+        Err(e) => Err(e.context("Voice note encoding failed")),
+    }
+}
+
+/// Join TTS clips (WAV or Ogg/Opus) in order and encode them as voice notes of
+/// at most `max_secs` each, split between clips.
+pub async fn clips_to_voice_notes(
+    clips: Vec<Vec<u8>>,
+    max_secs: u32,
+    ffmpeg_fallback: bool,
+) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut decoded = Vec::with_capacity(clips.len());
+    for clip in clips {
+        let (clip, native) = tokio::task::spawn_blocking(move || {
+            let result = decode::decode(&clip);
+            (clip, result)
+        })
+        .await?;
+
+        decoded.push(match native {
+            Ok(audio) => audio,
+            Err(e) if ffmpeg_fallback => {
+                tracing::debug!("Native decoding failed ({:#}), trying ffmpeg", e);
+                decode::DecodedAudio {
+                    samples: ffmpeg::decode_to_pcm(&clip).await?,
+                    sample_rate: WHISPER_SAMPLE_RATE,
+                }
+            }
+            Err(e) => return Err(e.context("Unsupported TTS audio format")),
+        });
+    }
+
+    let parts = tokio::task::spawn_blocking(move || join::join(decoded, max_secs)).await??;
+
+    let mut notes = Vec::with_capacity(parts.len());
+    for part in parts {
+        notes.push(pcm_to_voice(part.samples, part.sample_rate, ffmpeg_fallback).await?);
+    }
+    Ok(notes)
+}
+
+/// Encode mono PCM as an Ogg/Opus voice note.
+async fn pcm_to_voice(pcm: Vec<f32>, sample_rate: u32, ffmpeg_fallback: bool) -> anyhow::Result<Vec<u8>> {
+    let (pcm, native) = tokio::task::spawn_blocking(move || {
+        let result = resample::resample(&pcm, sample_rate, opus::SAMPLE_RATE)
+            .and_then(|resampled| opus::encode_ogg(&resampled, sample_rate));
+        (pcm, result)
+    })
+    .await?;
+
+    match native {
+        Ok(ogg) => Ok(ogg),
+        Err(e) if ffmpeg_fallback => {
+            tracing::warn!("Native Opus encoding failed ({:#}), trying ffmpeg", e);
+            ffmpeg::wav_to_ogg(&wav::f32_to_wav(&pcm, sample_rate)).await
+        }
+        Err(e) => Err(e.context("Voice note encoding failed")),
+    }
+}
+
+/// A temp file path that is removed on drop.
+pub struct TempFile(pub PathBuf);
+
+impl TempFile {
+    pub fn new() -> Self {
+        Self(std::env::temp_dir().join(format!("tts_stt_bot-{}", Uuid::new_v4())))
+    }
+}
+
+impl Default for TempFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
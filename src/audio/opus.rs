@@ -0,0 +1,151 @@
+//! Ogg/Opus (Telegram voice notes): decoding with libopus and encoding for replies.
+
+use audiopus::coder::{Decoder, Encoder};
+use audiopus::packet::Packet;
+use audiopus::{Application, Bitrate, Channels, MutSignals, SampleRate};
+use ogg::writing::PacketWriteEndInfo;
+use ogg::{PacketReader, PacketWriter};
+use std::io::Cursor;
+
+use crate::audio::decode::{downmix_into, DecodedAudio};
+use crate::audio::WHISPER_SAMPLE_RATE;
+
+/// Rate Opus encodes at; granule positions are always counted in it.
+pub const SAMPLE_RATE: u32 = 48_000;
+/// 20 ms frames at 48 kHz.
+const FRAME_SAMPLES: usize = 960;
+/// Longest Opus frame (120 ms) at 16 kHz, per channel.
+const MAX_DECODED_FRAME: usize = 1920;
+/// Plenty for speech in a voice note.
+const VOICE_BITRATE: i32 = 32_000;
+
+/// Whether `data` is an Ogg stream whose first packet is an Opus header.
+pub fn is_ogg_opus(data: &[u8]) -> bool {
+    // Page header is 27 bytes plus one lacing byte per segment
+    data.starts_with(b"OggS")
+        && data.len() > 27
+        && data
+            .get(27 + data[26] as usize..)
+            .is_some_and(|body| body.starts_with(b"OpusHead"))
+}
+
+/// Decode an Ogg/Opus file straight to 16 kHz mono; libopus resamples internally.
+pub fn decode_ogg(data: &[u8]) -> anyhow::Result<DecodedAudio> {
+    let mut reader = PacketReader::new(Cursor::new(data));
+
+    let head = reader
+        .read_packet()?
+        .ok_or_else(|| anyhow::anyhow!("Empty Ogg stream"))?;
+    if head.data.len() < 19 || !head.data.starts_with(b"OpusHead") {
+        anyhow::bail!("Missing OpusHead");
+    }
+    let serial = head.stream_serial();
+    let channel_count = head.data[9] as usize;
+    let channels = match channel_count {
+        1 => Channels::Mono,
+        2 => Channels::Stereo,
+        n => anyhow::bail!("Unsupported Opus channel count: {}", n),
+    };
+    // Pre-skip and granule positions are in 48 kHz samples
+    let ratio = (SAMPLE_RATE / WHISPER_SAMPLE_RATE) as u64;
+    let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as u64;
+
+    let mut decoder = Decoder::new(SampleRate::Hz16000, channels)?;
+    let mut frame = vec![0.0f32; MAX_DECODED_FRAME * channel_count];
+    let mut samples = Vec::new();
+    let mut last_granule = 0;
+    let mut seen_tags = false;
+
+    while let Some(packet) = reader.read_packet()? {
+        if packet.stream_serial() != serial {
+            continue;
+        }
+        // The second packet is OpusTags metadata
+        if !seen_tags {
+            seen_tags = true;
+            continue;
+        }
+        last_granule = packet.absgp_page();
+        if packet.data.is_empty() {
+            continue;
+        }
+
+        let input = Packet::try_from(packet.data.as_slice())?;
+        let output = MutSignals::try_from(frame.as_mut_slice())?;
+        match decoder.decode_float(Some(input), output, false) {
+            Ok(decoded) => downmix_into(&mut samples, &frame[..decoded * channel_count], channel_count),
+            Err(e) => tracing::debug!("Skipping undecodable Opus packet: {}", e),
+        }
+    }
+
+    // Trim the encoder's pre-skip and the padding the final granule marks as unused
+    let start = ((pre_skip / ratio) as usize).min(samples.len());
+    let end = (last_granule / ratio) as usize;
+    if last_granule > 0 && end < samples.len() {
+        samples.truncate(end);
+    }
+    samples.drain(..start);
+
+    if samples.is_empty() {
+        anyhow::bail!("No audio decoded");
+    }
+    Ok(DecodedAudio {
+        samples,
+        sample_rate: WHISPER_SAMPLE_RATE,
+    })
+}
+
+/// Encode 48 kHz mono PCM as an Ogg/Opus voice note.
+/// `input_rate` is only recorded in the header as the original sample rate.
+pub fn encode_ogg(pcm: &[f32], input_rate: u32) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Voip)?;
+    encoder.set_bitrate(Bitrate::BitsPerSecond(VOICE_BITRATE))?;
+    let pre_skip = encoder.lookahead()? as usize;
+
+    let serial = uuid::Uuid::new_v4().as_u128() as u32;
+    let mut writer = PacketWriter::new(Vec::new());
+
+    let mut head = Vec::with_capacity(19);
+    head.extend_from_slice(b"OpusHead");
+    head.push(1); // version
+    head.push(1); // channels
+    head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+    head.extend_from_slice(&input_rate.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+    head.push(0); // channel mapping family
+    writer.write_packet(head.into_boxed_slice(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+    let vendor = concat!("tts_stt_bot ", env!("CARGO_PKG_VERSION"));
+    let mut tags = Vec::with_capacity(16 + vendor.len());
+    tags.extend_from_slice(b"OpusTags");
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor.as_bytes());
+    tags.extend_from_slice(&0u32.to_le_bytes()); // no user comments
+    writer.write_packet(tags.into_boxed_slice(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+    // Feed the lookahead worth of extra silence so the tail isn't cut off
+    let total = pcm.len() + pre_skip;
+    let frames = total.div_ceil(FRAME_SAMPLES).max(1);
+    let mut input = pcm.to_vec();
+    input.resize(frames * FRAME_SAMPLES, 0.0);
+
+    let mut packet = [0u8; 4000];
+    for (i, frame) in input.chunks_exact(FRAME_SAMPLES).enumerate() {
+        let len = encoder.encode_float(frame, &mut packet)?;
+        let last = i + 1 == frames;
+        // The final granule tells decoders where the real audio ends
+        let granule = if last {
+            (pre_skip + pcm.len()) as u64
+        } else {
+            ((i + 1) * FRAME_SAMPLES) as u64
+        };
+        let end_info = if last {
+            PacketWriteEndInfo::EndStream
+        } else {
+            PacketWriteEndInfo::NormalPacket
+        };
+        writer.write_packet(packet[..len].into(), serial, end_info, granule)?;
+    }
+
+    Ok(writer.into_inner())
+}
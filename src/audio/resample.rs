@@ -0,0 +1,35 @@
+use rubato::{FftFixedIn, Resampler};
+
+/// Input frames per resampler call.
+const CHUNK: usize = 1024;
+
+/// Resample mono audio from `from` Hz to `to` Hz (band-limited FFT resampler).
+pub fn resample(samples: &[f32], from: u32, to: u32) -> anyhow::Result<Vec<f32>> {
+    if from == to || samples.is_empty() {
+        return Ok(samples.to_vec());
+    }
+
+    let mut resampler = FftFixedIn::<f32>::new(from as usize, to as usize, CHUNK, 2, 1)?;
+    let delay = resampler.output_delay();
+    let expected = (samples.len() as u64 * to as u64 / from as u64) as usize;
+    let mut out = Vec::with_capacity(delay + expected + CHUNK);
+
+    let mut chunks = samples.chunks_exact(CHUNK);
+    for chunk in &mut chunks {
+        out.extend_from_slice(&resampler.process(&[chunk], None)?[0]);
+    }
+    out.extend_from_slice(&resampler.process_partial(Some(&[chunks.remainder()]), None)?[0]);
+
+    // Flush what the filter still holds
+    while out.len() < delay + expected {
+        let tail = resampler.process_partial::<&[f32]>(None, None)?;
+        if tail[0].is_empty() {
+            break;
+        }
+        out.extend_from_slice(&tail[0]);
+    }
+
+    // Drop the filter delay at the start and the zero padding at the end
+    let end = (delay + expected).min(out.len());
+    Ok(out[delay.min(end)..end].to_vec())
+}
@@ -2,7 +2,11 @@ use std::sync::Arc;
 use teloxide::prelude::*;
 use uuid::Uuid;
 
-use crate::ai::llm::{ChatMessage, LlmClient};
+use crate::agent::quota::{self, QuotaStatus};
+use crate::ai::llm::ChatMessage;
+use crate::ai::stt::language_label;
+use crate::ai::subtitles::TranscriptFormat;
+use crate::ai::whisper_models::ModelTier;
 use crate::bot::AppState;
 
 pub async fn handle_callback(
@@ -19,18 +23,19 @@ pub async fn handle_callback(
 
     // ── TTS Engine Selection ───────────────────────────────────────
     if let Some(engine) = data.strip_prefix("set_tts:") {
+        let Some(backend) = state.tts.get(engine) else {
+            bot.answer_callback_query(&q.id).await?;
+            return Ok(());
+        };
         let mut settings = state.db.get_user_settings(user_id).await?;
-        settings["tts_engine"] = serde_json::json!(engine);
+        settings["tts_engine"] = serde_json::json!(backend.id());
         state.db.update_user_settings(user_id, &settings).await?;
 
-        // Reset XTTS availability cache so it gets retried
-        if engine == "xtts" {
-            state.tts.reset_xtts_availability();
-        }
+        // Picking an engine retries it even if it was down
+        backend.reset();
 
-        let display = crate::ai::tts::TtsEngine::from_str_loose(engine).display_name();
         bot.answer_callback_query(&q.id)
-            .text(format!("TTS set to: {}", display))
+            .text(format!("TTS set to: {}", backend.display_name()))
             .await?;
 
         return Ok(());
@@ -45,6 +50,7 @@ pub async fn handle_callback(
         let label = match mode {
             "text" => "🔤 Text Only",
             "voice" => "🎙 Voice Only",
+            "transcribe" => "📝 Transcribe Only",
             _ => "🤖 Auto",
         };
         bot.answer_callback_query(&q.id)
@@ -54,6 +60,96 @@ pub async fn handle_callback(
         return Ok(());
     }
 
+    // ── Speech Language Selection ──────────────────────────────────
+    if let Some(language) = data.strip_prefix("set_lang:") {
+        let mut settings = state.db.get_user_settings(user_id).await?;
+        settings["stt_language"] = serde_json::json!(language);
+        state.db.update_user_settings(user_id, &settings).await?;
+
+        bot.answer_callback_query(&q.id)
+            .text(format!("Speech language: {}", language_label(language)))
+            .await?;
+
+        return Ok(());
+    }
+
+    // ── Transcript Format Selection ────────────────────────────────
+    if let Some(format) = data.strip_prefix("set_tsfmt:") {
+        let format = TranscriptFormat::from_str_loose(format);
+        let mut settings = state.db.get_user_settings(user_id).await?;
+        settings["transcript_format"] = serde_json::json!(format.as_str());
+        state.db.update_user_settings(user_id, &settings).await?;
+
+        bot.answer_callback_query(&q.id)
+            .text(format!("Transcript format: {}", format.display_name()))
+            .await?;
+
+        return Ok(());
+    }
+
+    // ── Speech Model Tier Selection ────────────────────────────────
+    if let Some(tier) = data.strip_prefix("set_tier:") {
+        let Some(tier) = ModelTier::from_str_loose(tier) else {
+            bot.answer_callback_query(&q.id).await?;
+            return Ok(());
+        };
+        let mut settings = state.db.get_user_settings(user_id).await?;
+        settings["stt_tier"] = serde_json::json!(tier.as_str());
+        state.db.update_user_settings(user_id, &settings).await?;
+
+        bot.answer_callback_query(&q.id)
+            .text(format!("Speech model: {}", tier.display_name()))
+            .await?;
+
+        return Ok(());
+    }
+
+    // ── Noise Suppression Toggle ───────────────────────────────────
+    if let Some(choice) = data.strip_prefix("set_denoise:") {
+        let mut settings = state.db.get_user_settings(user_id).await?;
+        let text = match choice {
+            "on" | "off" => {
+                settings["denoise"] = serde_json::json!(choice == "on");
+                format!("Noise suppression: {}", if choice == "on" { "On" } else { "Off" })
+            }
+            "debug_on" | "debug_off" if state.config.is_admin(user_id) => {
+                settings["denoise_debug"] = serde_json::json!(choice == "debug_on");
+                format!(
+                    "Denoise debug audio: {}",
+                    if choice == "debug_on" { "On" } else { "Off" }
+                )
+            }
+            _ => {
+                bot.answer_callback_query(&q.id).await?;
+                return Ok(());
+            }
+        };
+        state.db.update_user_settings(user_id, &settings).await?;
+
+        bot.answer_callback_query(&q.id).text(text).await?;
+
+        return Ok(());
+    }
+
+    // ── Voice Picker ───────────────────────────────────────────────
+    if let Some(action) = data.strip_prefix("voice_") {
+        crate::bot::voices::handle_callback(&bot, &q, &state, user_id, action).await?;
+        return Ok(());
+    }
+
+    // ── Transcription Cancel ───────────────────────────────────────
+    if let Some(job_id_str) = data.strip_prefix("cancel_stt:") {
+        let cancelled = Uuid::parse_str(job_id_str)
+            .map(|job_id| state.stt.cancel(job_id, user_id))
+            .unwrap_or(false);
+
+        bot.answer_callback_query(&q.id)
+            .text(if cancelled { "Cancelling…" } else { "Nothing to cancel" })
+            .await?;
+
+        return Ok(());
+    }
+
     // ── Conversation Selection ─────────────────────────────────────
     if let Some(conv_id_str) = data.strip_prefix("conv:") {
         if let Ok(conv_id) = Uuid::parse_str(conv_id_str) {
@@ -85,9 +181,15 @@ pub async fn handle_callback(
                         .map(|c| c.summary.clone())
                         .unwrap_or_default();
 
-                    let summary_text = if existing_summary.is_empty() {
+                    let settings = state.db.get_user_settings(user_id).await?;
+                    let quota_status =
+                        QuotaStatus::load(&state.db, &state.config, user_id, &settings).await?;
+
+                    let summary_text = if existing_summary.is_empty()
+                        && quota_status.exceeded_message().is_none()
+                    {
                         // Auto-generate summary from recent messages using LLM
-                        match generate_conversation_summary(&state.llm, &messages).await {
+                        match generate_conversation_summary(&state, user_id, conv_id, &messages).await {
                             Ok(generated) => {
                                 // Save it for future use
                                 let _ = state.db.update_conversation_summary(conv_id, &generated).await;
@@ -98,6 +200,8 @@ pub async fn handle_callback(
                                 format!("{} messages in this conversation", msg_count)
                             }
                         }
+                    } else if existing_summary.is_empty() {
+                        format!("{} messages in this conversation", msg_count)
                     } else {
                         existing_summary
                     };
@@ -154,7 +258,9 @@ pub async fn handle_callback(
 
 /// Generate a brief conversation summary using the LLM.
 async fn generate_conversation_summary(
-    llm: &LlmClient,
+    state: &AppState,
+    user_id: i64,
+    conv_id: Uuid,
     messages: &[crate::db::models::Message],
 ) -> anyhow::Result<String> {
     // Take last ~10 messages for summary
@@ -176,13 +282,16 @@ async fn generate_conversation_summary(
         ChatMessage {
             role: "system".to_string(),
             content: "Summarize this conversation in 1-2 short sentences. Be concise and capture the key topic.".to_string(),
+            ..Default::default()
         },
         ChatMessage {
             role: "user".to_string(),
             content: conversation_text,
+            ..Default::default()
         },
     ];
 
-    let response = llm.chat(&prompt).await?;
+    let response = state.llm.chat(&prompt).await?;
+    quota::record(&state.db, user_id, Some(conv_id), "conversation_summary", &response).await?;
     Ok(response.text.trim().to_string())
 }
@@ -4,8 +4,10 @@ use teloxide::prelude::*;
 use teloxide::utils::command::BotCommands as _;
 use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
 
-use crate::ai::tts::TtsEngine;
-use crate::bot::AppState;
+use crate::agent::quota::QuotaStatus;
+use crate::ai::stt::{language_label, STT_LANGUAGES};
+use crate::ai::subtitles::TranscriptFormat;
+use crate::bot::{media, voices, AppState, GLOBAL_VOCABULARY_KEY};
 
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase", description = "Available commands:")]
@@ -18,10 +20,18 @@ pub enum BotCommand {
     History,
     #[command(description = "Open settings menu")]
     Settings,
+    #[command(description = "Transcribe the replied-to (or next) recording without the AI [txt|srt|vtt]")]
+    Transcribe(String),
+    #[command(description = "Names and terms transcription should spell right [add|remove|clear]")]
+    Vocab(String),
+    #[command(description = "Pick the voice replies are spoken in [voice id] [speaker]")]
+    Voice(String),
     #[command(description = "Show token & context usage")]
     Usage,
     #[command(description = "Change model (admin only)")]
     Model(String),
+    #[command(description = "View or set a user's token quota (admin only)")]
+    Quota(String),
     #[command(description = "Show help")]
     Help,
 }
@@ -103,35 +113,36 @@ pub async fn handle_command(
 
         BotCommand::Settings => {
             let settings: serde_json::Value = state.db.get_user_settings(user_id).await?;
-            let current_engine = settings
-                .get("tts_engine")
-                .and_then(|v| v.as_str())
-                .unwrap_or("piper");
+            let current_engine = state.tts.for_settings(&settings);
             let current_mode = settings
                 .get("response_mode")
                 .and_then(|v| v.as_str())
                 .unwrap_or("auto");
-
-            let display_name = TtsEngine::from_str_loose(current_engine).display_name();
+            let current_language = settings
+                .get("stt_language")
+                .and_then(|v| v.as_str())
+                .unwrap_or(&state.config.default_stt_language);
+            let current_format = media::transcript_format(&settings);
+            let current_tier = media::stt_tier(&settings, &state.config);
+            let denoise = media::denoise_enabled(&settings, &state.config);
 
             let keyboard = InlineKeyboardMarkup::new(vec![
-                // Row 1: TTS Engine
-                vec![
-                    InlineKeyboardButton::callback(
-                        format!(
-                            "{} Piper (Fast)",
-                            if current_engine == "piper" { "✅" } else { "⬜" }
-                        ),
-                        "set_tts:piper",
-                    ),
-                    InlineKeyboardButton::callback(
-                        format!(
-                            "{} XTTS (Quality)",
-                            if current_engine == "xtts" { "✅" } else { "⬜" }
-                        ),
-                        "set_tts:xtts",
-                    ),
-                ],
+                // Row 1: TTS engines, as registered from config
+                state
+                    .tts
+                    .backends()
+                    .iter()
+                    .map(|backend| {
+                        InlineKeyboardButton::callback(
+                            format!(
+                                "{} {}",
+                                if backend.id() == current_engine.id() { "✅" } else { "⬜" },
+                                backend.display_name()
+                            ),
+                            format!("set_tts:{}", backend.id()),
+                        )
+                    })
+                    .collect(),
                 // Row 2: Response Mode
                 vec![
                     InlineKeyboardButton::callback(
@@ -155,7 +166,75 @@ pub async fn handle_command(
                         ),
                         "set_mode:auto",
                     ),
+                    InlineKeyboardButton::callback(
+                        format!(
+                            "{} Transcribe",
+                            if current_mode == "transcribe" { "✅" } else { "⬜" }
+                        ),
+                        "set_mode:transcribe",
+                    ),
                 ],
+                // Row 3: Speech (STT) language
+                STT_LANGUAGES
+                    .iter()
+                    .map(|(code, label)| {
+                        InlineKeyboardButton::callback(
+                            format!("{} {}", if current_language == *code { "✅" } else { "⬜" }, label),
+                            format!("set_lang:{}", code),
+                        )
+                    })
+                    .collect(),
+                // Row 4: Transcript format (transcribe mode and /transcribe)
+                [TranscriptFormat::Text, TranscriptFormat::Srt, TranscriptFormat::Vtt]
+                    .into_iter()
+                    .map(|format| {
+                        InlineKeyboardButton::callback(
+                            format!(
+                                "{} {}",
+                                if current_format == format { "✅" } else { "⬜" },
+                                format.display_name()
+                            ),
+                            format!("set_tsfmt:{}", format.as_str()),
+                        )
+                    })
+                    .collect(),
+                // Row 5: Whisper model tier (speed vs accuracy), for the models installed
+                state
+                    .stt_models
+                    .available_tiers()
+                    .into_iter()
+                    .map(|tier| {
+                        InlineKeyboardButton::callback(
+                            format!(
+                                "{} {}",
+                                if current_tier == tier { "✅" } else { "⬜" },
+                                tier.display_name()
+                            ),
+                            format!("set_tier:{}", tier.as_str()),
+                        )
+                    })
+                    .collect(),
+                // Row 6: Noise suppression, plus before/after debug audio for admins
+                {
+                    let mut row = vec![
+                        InlineKeyboardButton::callback(
+                            format!("{} Denoise On", if denoise { "✅" } else { "⬜" }),
+                            "set_denoise:on",
+                        ),
+                        InlineKeyboardButton::callback(
+                            format!("{} Denoise Off", if denoise { "⬜" } else { "✅" }),
+                            "set_denoise:off",
+                        ),
+                    ];
+                    if state.config.is_admin(user_id) {
+                        let debug = media::denoise_debug(&settings, user_id, &state.config);
+                        row.push(InlineKeyboardButton::callback(
+                            format!("{} Debug Audio", if debug { "✅" } else { "⬜" }),
+                            if debug { "set_denoise:debug_off" } else { "set_denoise:debug_on" },
+                        ));
+                    }
+                    row
+                },
             ]);
 
             bot.send_message(
@@ -163,16 +242,68 @@ pub async fn handle_command(
                 format!(
                     "⚙️ Settings\n\n\
                      🎵 TTS Engine: {}\n\
-                     📨 Response Mode: {}\n\n\
+                     📨 Response Mode: {}\n\
+                     🗣 Speech Language: {}\n\
+                     📝 Transcript Format: {}\n\
+                     🧠 Speech Model: {}\n\
+                     🔇 Noise Suppression: {}\n\n\
                      Select your preferences:",
-                    display_name,
+                    current_engine.display_name(),
                     response_mode_label(current_mode),
+                    language_label(current_language),
+                    current_format.display_name(),
+                    current_tier.display_name(),
+                    if denoise { "On" } else { "Off" },
                 ),
             )
             .reply_markup(keyboard)
             .await?;
         }
 
+        BotCommand::Transcribe(format_arg) => {
+            let mut settings: serde_json::Value = state.db.get_user_settings(user_id).await?;
+            let format = if format_arg.trim().is_empty() {
+                media::transcript_format(&settings)
+            } else {
+                TranscriptFormat::from_str_loose(&format_arg)
+            };
+
+            // Replying to a recording transcribes it right away
+            if let Some(source) = msg.reply_to_message().and_then(media::audio_source) {
+                if let Some(transcription) =
+                    media::transcribe_source(&bot, &state, msg.chat.id, user_id, &source, &settings).await?
+                {
+                    media::send_transcript(&bot, msg.chat.id, &transcription, format).await?;
+                }
+            } else {
+                settings["transcribe_next"] = serde_json::json!(format.as_str());
+                state.db.update_user_settings(user_id, &settings).await?;
+                bot.send_message(
+                    msg.chat.id,
+                    "🎙 Send a voice message, audio or video and I'll reply with just the transcript.",
+                )
+                .await?;
+            }
+        }
+
+        BotCommand::Vocab(args) => {
+            let reply = vocab_command(&state, user_id, &args).await?;
+            bot.send_message(msg.chat.id, reply).await?;
+        }
+
+        BotCommand::Voice(args) => {
+            if args.trim().is_empty() {
+                let settings = state.db.get_user_settings(user_id).await?;
+                let (text, keyboard) = voices::language_menu(&state, &settings);
+                bot.send_message(msg.chat.id, text)
+                    .reply_markup(keyboard)
+                    .await?;
+            } else {
+                let reply = voices::set_voice_command(&state, user_id, &args).await?;
+                bot.send_message(msg.chat.id, reply).await?;
+            }
+        }
+
         BotCommand::Usage => {
             let settings: serde_json::Value = state.db.get_user_settings(user_id).await?;
 
@@ -185,12 +316,26 @@ pub async fn handle_command(
                 .and_then(|v| v.as_str())
             {
                 if let Ok(conv_id) = uuid::Uuid::parse_str(conv_id_str) {
-                    let total_tokens = state.db.get_total_tokens(conv_id).await.unwrap_or(0);
                     let messages: Vec<crate::db::models::Message> = state.db.get_messages(conv_id).await.unwrap_or_default();
                     let msg_count = messages.len();
+                    let context_tokens = crate::agent::context::context_tokens(&messages);
+                    let (prompt_tokens, completion_tokens) =
+                        state.db.get_conversation_usage(conv_id).await.unwrap_or((0, 0));
+                    let last_model = messages
+                        .iter()
+                        .rev()
+                        .find_map(|m| m.model.as_deref())
+                        .map(|m| format!("\n🔁 Last reply by: {}", m))
+                        .unwrap_or_default();
                     format!(
-                        "💬 Messages: {}\n🧠 Context: {} / {} tokens",
-                        msg_count, total_tokens, state.config.max_context_tokens
+                        "💬 Messages: {}\n🧠 Context: {} / {} tokens\n\
+                         📊 API usage: {} prompt + {} completion tokens{}",
+                        msg_count,
+                        context_tokens,
+                        state.config.max_context_tokens,
+                        prompt_tokens,
+                        completion_tokens,
+                        last_model
                     )
                 } else {
                     "💬 No active conversation".to_string()
@@ -203,21 +348,23 @@ pub async fn handle_command(
                 .get("response_mode")
                 .and_then(|v| v.as_str())
                 .unwrap_or("auto");
-            let tts_engine = settings
-                .get("tts_engine")
-                .and_then(|v| v.as_str())
-                .unwrap_or("piper");
+            let tts_engine = state.tts.for_settings(&settings);
+
+            let quota_status =
+                QuotaStatus::load(&state.db, &state.config, user_id, &settings).await?;
 
             let usage_text = format!(
                 "📊 Usage & Context\n\n\
                  🤖 Model: {}\n\
                  {}\n\
+                 {}\n\
                  📨 Response mode: {}\n\
                  🎵 TTS engine: {}",
                 current_model,
                 conv_info,
+                quota_status.describe(),
                 response_mode_label(response_mode),
-                TtsEngine::from_str_loose(tts_engine).display_name(),
+                tts_engine.display_name(),
             );
 
             bot.send_message(msg.chat.id, usage_text).await?;
@@ -247,6 +394,15 @@ pub async fn handle_command(
             }
         }
 
+        BotCommand::Quota(args) => {
+            let reply = if state.config.is_admin(user_id) {
+                quota_command(&state, &args).await?
+            } else {
+                "❌ Only admins can manage quotas.".to_string()
+            };
+            bot.send_message(msg.chat.id, reply).await?;
+        }
+
         BotCommand::Help => {
             bot.send_message(msg.chat.id, BotCommand::descriptions().to_string())
                 .await?;
@@ -256,11 +412,128 @@ pub async fn handle_command(
     Ok(())
 }
 
+/// `/quota <user_id>` shows a user's usage, `/quota <user_id> <daily> <monthly>`
+/// overrides their quota (0 = unlimited), `/quota <user_id> reset` restores the role default.
+async fn quota_command(state: &AppState, args: &str) -> anyhow::Result<String> {
+    const USAGE: &str = "Usage:\n\
+                         /quota <user_id>\n\
+                         /quota <user_id> <daily> <monthly>  (0 = unlimited)\n\
+                         /quota <user_id> reset";
+
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let Some(target) = parts.first().and_then(|s| s.parse::<i64>().ok()) else {
+        return Ok(USAGE.to_string());
+    };
+
+    let Ok(mut settings) = state.db.get_user_settings(target).await else {
+        return Ok(format!("❌ User {} not found.", target));
+    };
+
+    match parts[1..] {
+        [] => {}
+        ["reset"] => {
+            if let Some(obj) = settings.as_object_mut() {
+                obj.remove("quota_daily");
+                obj.remove("quota_monthly");
+            }
+            state.db.update_user_settings(target, &settings).await?;
+        }
+        [daily, monthly] => {
+            let (Ok(daily), Ok(monthly)) = (daily.parse::<i64>(), monthly.parse::<i64>()) else {
+                return Ok(USAGE.to_string());
+            };
+            settings["quota_daily"] = serde_json::json!(daily);
+            settings["quota_monthly"] = serde_json::json!(monthly);
+            state.db.update_user_settings(target, &settings).await?;
+            tracing::info!("Quota for user {} set to {}/day, {}/month", target, daily, monthly);
+        }
+        _ => return Ok(USAGE.to_string()),
+    }
+
+    let status = QuotaStatus::load(&state.db, &state.config, target, &settings).await?;
+    Ok(format!("👤 User {}\n{}", target, status.describe()))
+}
+
+/// `/vocab` lists the glossary, `/vocab add|remove <terms>` and `/vocab clear` edit
+/// the user's own terms; admins edit the global glossary with `/vocab global ...`.
+async fn vocab_command(state: &AppState, user_id: i64, args: &str) -> anyhow::Result<String> {
+    const USAGE: &str = "Usage:\n\
+                         /vocab add <term>, <term>, ...\n\
+                         /vocab remove <term>, <term>, ...\n\
+                         /vocab clear\n\
+                         /vocab global add|remove|clear ...  (admin only)";
+
+    let args = args.trim();
+    let (global, args) = match args.strip_prefix("global") {
+        Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => (true, rest.trim()),
+        _ => (false, args),
+    };
+    let (action, terms) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let terms: Vec<String> = terms
+        .split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    if global && !state.config.is_admin(user_id) {
+        return Ok("❌ Only admins can change the global vocabulary.".to_string());
+    }
+
+    let mut settings = state.db.get_user_settings(user_id).await?;
+    if !action.is_empty() {
+        let mut list = if global {
+            state.global_vocabulary.read().await.clone()
+        } else {
+            media::user_vocabulary(&settings)
+        };
+
+        match (action, terms.is_empty()) {
+            ("add", false) => {
+                for term in terms {
+                    if !list.iter().any(|t| t.eq_ignore_ascii_case(&term)) {
+                        list.push(term);
+                    }
+                }
+            }
+            ("remove", false) => list.retain(|t| !terms.iter().any(|r| r.eq_ignore_ascii_case(t))),
+            ("clear", true) => list.clear(),
+            _ => return Ok(USAGE.to_string()),
+        }
+
+        if global {
+            tracing::info!("Admin {} set the global vocabulary to {:?}", user_id, list);
+            state
+                .db
+                .set_bot_setting(GLOBAL_VOCABULARY_KEY, &serde_json::json!(list))
+                .await?;
+            *state.global_vocabulary.write().await = list;
+        } else {
+            settings["vocabulary"] = serde_json::json!(list);
+            state.db.update_user_settings(user_id, &settings).await?;
+        }
+    }
+
+    let describe = |terms: &[String]| {
+        if terms.is_empty() {
+            "—".to_string()
+        } else {
+            terms.join(", ")
+        }
+    };
+    Ok(format!(
+        "📖 Vocabulary\n\nYours: {}\nGlobal: {}\n\n{}",
+        describe(&media::user_vocabulary(&settings)),
+        describe(&state.global_vocabulary.read().await),
+        USAGE
+    ))
+}
+
 /// Human-readable label for response mode.
 fn response_mode_label(mode: &str) -> &str {
     match mode {
         "text" => "🔤 Text Only",
         "voice" => "🎙 Voice Only",
+        "transcribe" => "📝 Transcribe Only",
         _ => "🤖 Auto (match input)",
     }
 }
@@ -1,4 +1,8 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
 use teloxide::net::Download;
 use teloxide::prelude::*;
 use teloxide::types::InputFile;
@@ -7,10 +11,22 @@ use uuid::Uuid;
 use crate::agent::context::ContextManager;
 use crate::agent::executor::{CommandExecutor, ExecutionResult};
 use crate::agent::identity::IdentityManager;
-use crate::agent::tools::ToolRegistry;
-use crate::ai::llm::{ChatMessage, LlmClient};
-use crate::ai::tts::TtsEngine;
+use crate::agent::quota::{self, QuotaStatus};
+use crate::agent::tools::{ToolCall, ToolDefinition, ToolRegistry};
+use crate::ai::llm::{ChatMessage, LlmResponse, StreamEvent};
+use crate::ai::provider::ApiError;
+use crate::ai::sentences::split_for_speech;
+use crate::ai::stt::AUTO_LANGUAGE;
+use crate::ai::tokenizer::count_tokens;
+use crate::ai::tts::{AudioFormat, SpeakOptions};
+use crate::audio;
+use crate::bot::media;
+use crate::bot::streaming::{split_point, StreamingReply, MAX_MESSAGE_LEN};
 use crate::bot::AppState;
+use crate::db::models::NewMessage;
+
+/// Question sent with a photo that has no caption.
+const DEFAULT_IMAGE_PROMPT: &str = "Describe this image.";
 
 /// Main message handler — wraps the inner logic in an error boundary
 /// so the user always gets feedback, even on errors.
@@ -21,7 +37,13 @@ pub async fn handle_message(
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     if let Err(e) = handle_message_inner(&bot, &msg, &state).await {
         tracing::error!("Handler error for chat {}: {:?}", msg.chat.id, e);
-        let error_msg = format!("⚠️ Something went wrong: {}", e);
+        let error_msg = match e.downcast_ref::<ApiError>() {
+            Some(api) if api.status == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                "⏳ The AI service is rate-limiting us right now. Please try again in a minute."
+                    .to_string()
+            }
+            _ => format!("⚠️ Something went wrong: {}", e),
+        };
         // Truncate error message if too long
         let truncated = if error_msg.len() > 4000 {
             format!("{}...", &error_msg[..4000])
@@ -45,34 +67,63 @@ async fn handle_message_inner(
 
     // Ensure user exists
     let user = state.db.get_or_create_user(user_id, username).await?;
+    let stt_language = media::stt_language(&user.settings, &state.config);
+
+    // Transcribe-only: reply with the transcript, skip the LLM and the conversation
+    if let Some(format) = media::transcript_request(&user.settings) {
+        if let Some(source) = media::audio_source(msg) {
+            if let Some(transcription) =
+                media::transcribe_source(bot, state, msg.chat.id, user_id, &source, &user.settings).await?
+            {
+                media::send_transcript(bot, msg.chat.id, &transcription, format).await?;
+            }
 
-    // ── 1. Extract text (from text message or voice transcription) ──
-
-    let user_text = if let Some(voice) = msg.voice() {
-        // Download voice message
-        bot.send_chat_action(msg.chat.id, teloxide::types::ChatAction::Typing)
-            .await?;
-
-        let file = bot.get_file(&voice.file.id).await?;
-        let mut buf = Vec::new();
-        bot.download_file(&file.path, &mut buf).await?;
+            if user.settings.get("transcribe_next").is_some() {
+                let mut settings = user.settings.clone();
+                if let Some(obj) = settings.as_object_mut() {
+                    obj.remove("transcribe_next");
+                }
+                state.db.update_user_settings(user_id, &settings).await?;
+            }
+            return Ok(());
+        }
+    }
 
-        // Convert OGG to PCM using ffmpeg
-        let pcm = ogg_to_pcm(&buf).await?;
+    // Refuse before any transcription or LLM work once the token quota is spent
+    let quota_status = QuotaStatus::load(&state.db, &state.config, user_id, &user.settings).await?;
+    if let Some(notice) = quota_status.exceeded_message() {
+        bot.send_message(msg.chat.id, notice).await?;
+        return Ok(());
+    }
 
-        // Transcribe
-        let text = state.stt.transcribe(&pcm)?;
-        tracing::info!("Transcribed voice from user {}: {}", user_id, &text);
+    // ── 1. Extract text (from text, audio/video transcription, or photo caption) ──
 
-        if text.is_empty() {
-            bot.send_message(msg.chat.id, "🤔 I couldn't understand that voice message.")
-                .await?;
+    let mut image_file_id = None;
+    // Spoken language of a transcribed message (detected in auto mode)
+    let mut spoken_language = None;
+    let user_text = if let Some(source) = media::audio_source(msg) {
+        let Some(transcription) =
+            media::transcribe_source(bot, state, msg.chat.id, user_id, &source, &user.settings).await?
+        else {
             return Ok(());
-        }
-
-        text
+        };
+        tracing::info!(
+            "Transcribed {} from user {} [{}]: {}",
+            source.kind,
+            user_id,
+            transcription.language,
+            &transcription.text
+        );
+
+        spoken_language = Some(transcription.language);
+        transcription.text
     } else if let Some(text) = msg.text() {
         text.to_string()
+    } else if let Some(photo) = msg.photo().and_then(|sizes| sizes.last()) {
+        // Telegram lists sizes ascending; keep the largest. The image itself is
+        // downloaded when the history is built.
+        image_file_id = Some(photo.file.id.clone());
+        msg.caption().unwrap_or(DEFAULT_IMAGE_PROMPT).to_string()
     } else {
         // Unsupported message type
         return Ok(());
@@ -98,17 +149,26 @@ async fn handle_message_inner(
 
     // ── 3. Save user message to DB ─────────────────────────────────
 
-    let token_count = LlmClient::estimate_tokens(&user_text);
+    let current_model = state.model_override.read().await.clone();
     state
         .db
-        .save_message(conv_id, "user", &user_text, token_count)
+        .insert_message(
+            conv_id,
+            &NewMessage {
+                role: "user",
+                content: &user_text,
+                token_count: count_tokens(&current_model, &user_text),
+                image_file_id: image_file_id.as_deref(),
+                ..Default::default()
+            },
+        )
         .await?;
 
     // ── 4. Check context limits and prune if needed ────────────────
 
     let context_mgr = ContextManager::new(state.config.max_context_tokens);
     context_mgr
-        .check_and_prune(&state.db, &state.llm, conv_id)
+        .check_and_prune(&state.db, &state.llm, user_id, conv_id)
         .await?;
 
     // ── 5. Build system prompt ─────────────────────────────────────
@@ -117,7 +177,11 @@ async fn handle_message_inner(
     let tool_registry = ToolRegistry::new();
 
     let system_prompt = identity_mgr
-        .build_system_prompt(&user.profile_summary, &tool_registry.describe_for_prompt())
+        .build_system_prompt(
+            &user.profile_summary,
+            &tool_registry.describe_for_prompt(),
+            spoken_language.as_deref(),
+        )
         .await?;
 
     // ── 6. Build message history for LLM ───────────────────────────
@@ -126,162 +190,270 @@ async fn handle_message_inner(
     let mut llm_messages = vec![ChatMessage {
         role: "system".to_string(),
         content: system_prompt,
+        ..Default::default()
     }];
-
-    for m in &db_messages {
-        llm_messages.push(ChatMessage {
-            role: m.role.clone(),
-            content: m.content.clone(),
-        });
+    // The newest photo still in the context is sent again, so follow-ups about it
+    // keep working; older ones are remembered through their caption and answer
+    let mut images = HashMap::new();
+    let newest_image = db_messages
+        .iter()
+        .rev()
+        .find_map(|m| m.image_file_id.as_deref().map(|file_id| (m.id, file_id)));
+    if let Some((message_id, file_id)) = newest_image {
+        match download_image_data_url(bot, file_id).await {
+            Ok(url) => {
+                images.insert(message_id, url);
+            }
+            Err(e) => tracing::warn!("Could not load image {}: {}", file_id, e),
+        }
     }
+    llm_messages.extend(history_to_chat_messages(&db_messages, &images));
 
-    // ── 7. Call LLM (with runtime model override) ──────────────────
+    // A photo needs a vision-capable model; turns without one use the chat model (or /model)
+    let model = if images.is_empty() {
+        current_model.clone()
+    } else {
+        state.config.vision_model.clone()
+    };
 
-    bot.send_chat_action(msg.chat.id, teloxide::types::ChatAction::Typing)
-        .await?;
+    // ── 7. Determine response mode (text, voice, or auto) ──────────
 
-    let current_model = state.model_override.read().await.clone();
-    let response = state.llm.chat_with_model(&llm_messages, &current_model).await?;
-    let mut assistant_text = response.text.clone();
+    let response_mode = settings
+        .get("response_mode")
+        .and_then(|v| v.as_str())
+        .unwrap_or("auto");
 
-    // ── 8. Check for tool calls ────────────────────────────────────
+    let should_voice = match response_mode {
+        "text" | "transcribe" => false,
+        "voice" => true,
+        _ /* auto */ => msg.voice().is_some() || msg.video_note().is_some(),
+    };
 
-    if let Some(tool_call) = ToolRegistry::parse_tool_call(&assistant_text) {
-        tracing::info!("Tool call detected: {:?}", tool_call);
+    // Text replies are streamed into a progressively edited message
+    let mut stream_reply = if should_voice {
+        None
+    } else {
+        Some(StreamingReply::start(bot, msg.chat.id).await?)
+    };
 
-        match tool_call.name.as_str() {
-            "run_command" => {
-                if let Some(cmd) = tool_call.arguments.get("command").and_then(|v| v.as_str()) {
-                    match CommandExecutor::execute(&state.db, cmd, user_id, chat_id).await? {
-                        ExecutionResult::Immediate(output) => {
-                            assistant_text = format!("Command output:\n```\n{}\n```", output);
-                        }
-                        ExecutionResult::PendingApproval(approval_id) => {
-                            // Send to admin group
-                            crate::agent::approval::request_approval(
-                                bot,
-                                state.config.admin_group_id,
-                                cmd,
-                                user_id,
-                                approval_id,
-                            )
-                            .await?;
-                            assistant_text =
-                                "⏳ That command needs admin approval. I've sent the request."
-                                    .to_string();
-                        }
-                        ExecutionResult::Blocked => {
-                            assistant_text =
-                                "🚫 That command is blocked for safety reasons.".to_string();
+    // ── 8. Agent loop: call LLM, run tools, feed results back ──────
+
+    let max_steps = state.config.max_agent_steps;
+    let mut step = 0;
+
+    let final_response = loop {
+        bot.send_chat_action(msg.chat.id, teloxide::types::ChatAction::Typing)
+            .await?;
+
+        // Once the step budget is spent, withhold tools to force a final answer
+        let tools = if step < max_steps {
+            tool_registry.definitions()
+        } else {
+            llm_messages.push(ChatMessage {
+                role: "system".to_string(),
+                content: "Tool step limit reached. Answer the user with the information you have."
+                    .to_string(),
+                ..Default::default()
+            });
+            &[]
+        };
+
+        let response = match stream_reply.as_mut() {
+            Some(reply) => match stream_completion(state, reply, &llm_messages, &model, tools).await {
+                Ok(response) => response,
+                Err(e) => {
+                    // Don't leave the placeholder behind; keep whatever was streamed
+                    if let Some(reply) = stream_reply.take() {
+                        if let Err(e) = reply.finish().await {
+                            tracing::warn!("Could not clean up the streamed reply: {}", e);
                         }
                     }
+                    return Err(e);
                 }
+            },
+            None => {
+                state
+                    .llm
+                    .chat_with_model(&llm_messages, &model, tools)
+                    .await?
             }
+        };
 
-            "update_persona" => {
-                if !state.config.is_admin(user_id) {
-                    assistant_text = "❌ Only admins can update persona files.".to_string();
-                } else if let (Some(file_name), Some(new_content)) = (
-                    tool_call
-                        .arguments
-                        .get("file_name")
-                        .and_then(|v| v.as_str()),
-                    tool_call
-                        .arguments
-                        .get("new_content")
-                        .and_then(|v| v.as_str()),
-                ) {
-                    identity_mgr.update_file(file_name, new_content).await?;
-                    assistant_text =
-                        format!("✅ Updated persona file: {}.md", file_name);
-                }
-            }
+        quota::record(&state.db, user_id, Some(conv_id), "chat", &response).await?;
 
-            _ => {
-                assistant_text = format!("Tool '{}' is not implemented yet.", tool_call.name);
-            }
+        if response.model != model {
+            tracing::info!("Reply for conv {} came from fallback model '{}'", conv_id, response.model);
         }
-    }
 
-    // ── 9. Save assistant response ─────────────────────────────────
+        if response.tool_calls.is_empty() {
+            break response;
+        }
+
+        if let Some(reply) = stream_reply.as_mut() {
+            reply.next_step().await?;
+        }
+
+        step += 1;
+        tracing::info!(
+            "Agent step {}/{} for conv {}: {} tool call(s)",
+            step,
+            max_steps,
+            conv_id,
+            response.tool_calls.len()
+        );
+
+        // ── 9. Persist the tool-call step and execute each call ────
+
+        let calls_json = serde_json::to_value(&response.tool_calls)?;
+        state
+            .db
+            .insert_message(
+                conv_id,
+                &NewMessage {
+                    role: "assistant",
+                    content: &response.text,
+                    token_count: count_tokens(&response.model, &response.text)
+                        + count_tokens(&response.model, &calls_json.to_string()),
+                    tool_calls: Some(&calls_json),
+                    model: Some(&response.model),
+                    prompt_tokens: response.usage.as_ref().map(|u| u.prompt_tokens as i32),
+                    completion_tokens: response.usage.as_ref().map(|u| u.completion_tokens as i32),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        llm_messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: response.text.clone(),
+            tool_calls: response.tool_calls.clone(),
+            ..Default::default()
+        });
+
+        for tool_call in &response.tool_calls {
+            tracing::info!("Tool call requested: {:?}", tool_call);
+            // Report failures back to the model instead of aborting the turn
+            let output =
+                match execute_tool_call(bot, state, &identity_mgr, tool_call, user_id, chat_id)
+                    .await
+                {
+                    Ok(output) => output,
+                    Err(e) => {
+                        tracing::warn!("Tool '{}' failed: {}", tool_call.name, e);
+                        format!("Error: {}", e)
+                    }
+                };
+
+            state
+                .db
+                .insert_message(
+                    conv_id,
+                    &NewMessage {
+                        role: "tool",
+                        content: &output,
+                        token_count: count_tokens(&response.model, &output),
+                        tool_call_id: Some(&tool_call.id),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            llm_messages.push(ChatMessage {
+                role: "tool".to_string(),
+                content: output,
+                tool_call_id: Some(tool_call.id.clone()),
+                ..Default::default()
+            });
+        }
+    };
+
+    // ── 10. Save assistant response ────────────────────────────────
 
-    let resp_tokens = LlmClient::estimate_tokens(&assistant_text);
+    let assistant_text = final_response.text;
     state
         .db
-        .save_message(conv_id, "assistant", &assistant_text, resp_tokens)
+        .insert_message(
+            conv_id,
+            &NewMessage {
+                role: "assistant",
+                content: &assistant_text,
+                token_count: count_tokens(&final_response.model, &assistant_text),
+                model: Some(&final_response.model),
+                prompt_tokens: final_response.usage.as_ref().map(|u| u.prompt_tokens as i32),
+                completion_tokens: final_response
+                    .usage
+                    .as_ref()
+                    .map(|u| u.completion_tokens as i32),
+                ..Default::default()
+            },
+        )
         .await?;
 
-    // ── 10. Determine response mode (text, voice, or auto) ─────────
-
-    let response_mode = settings
-        .get("response_mode")
-        .and_then(|v| v.as_str())
-        .unwrap_or("auto");
-
-    let should_voice = match response_mode {
-        "text" => false,
-        "voice" => true,
-        _ /* auto */ => msg.voice().is_some(),
-    };
+    // ── 11. Deliver the reply ──────────────────────────────────────
 
     if should_voice {
-        // Reply with voice — truncate for TTS (long text overwhelms engines)
-        const TTS_MAX_CHARS: usize = 500;
-        let tts_text = if assistant_text.len() > TTS_MAX_CHARS {
-            // Truncate at a sentence boundary if possible
-            let truncated = &assistant_text[..TTS_MAX_CHARS];
-            truncated
-                .rfind(". ")
-                .map(|i| &assistant_text[..=i])
-                .unwrap_or(truncated)
-        } else {
-            assistant_text.as_str()
+        let engine = state.tts.for_settings(&settings);
+
+        // Speak the user's language: detected, else their setting, else English
+        let tts_language = spoken_language
+            .as_deref()
+            .or(Some(stt_language.as_str()).filter(|l| *l != AUTO_LANGUAGE))
+            .unwrap_or("en");
+
+        let options = SpeakOptions::for_settings(&settings, tts_language);
+        let chunks = split_for_speech(&assistant_text, state.config.tts_chunk_chars);
+        let notes = match state.tts.speak_chunks(engine, &chunks, &options).await {
+            Ok(speech) => {
+                tracing::debug!(
+                    "Voice reply spoken in {} chunks by {}",
+                    speech.len(),
+                    speech.first().map(|s| s.engine.as_str()).unwrap_or_default()
+                );
+                // Telegram voice notes must be Ogg/Opus; a single Ogg/Opus clip already is one
+                if speech.len() == 1 && speech[0].format == AudioFormat::OggOpus {
+                    Ok(speech.into_iter().map(|s| s.audio).collect())
+                } else {
+                    let clips = speech.into_iter().map(|s| s.audio).collect();
+                    audio::clips_to_voice_notes(clips, state.config.tts_max_voice_secs, state.config.ffmpeg_fallback)
+                        .await
+                        .map_err(|e| e.context("Voice note encoding failed"))
+                }
+            }
+            Err(e) => Err(e.context("TTS failed")),
         };
 
-        let tts_engine_str = settings
-            .get("tts_engine")
-            .and_then(|v| v.as_str())
-            .unwrap_or(&state.config.default_tts_engine);
-        let engine = TtsEngine::from_str_loose(tts_engine_str);
-
-        match state.tts.speak(tts_text, &engine).await {
-            Ok(wav_bytes) => {
-                // Convert WAV to OGG for Telegram voice
-                match wav_to_ogg(&wav_bytes).await {
-                    Ok(ogg_bytes) => {
-                        let voice = InputFile::memory(ogg_bytes).file_name("response.ogg");
-                        bot.send_voice(msg.chat.id, voice).await?;
-                    }
-                    Err(e) => {
-                        tracing::error!("WAV→OGG conversion failed: {}", e);
-                        send_long_message(bot, msg.chat.id, &assistant_text).await?;
+        match notes {
+            Ok(notes) if !notes.is_empty() => {
+                let count = notes.len();
+                for (i, ogg_bytes) in notes.into_iter().enumerate() {
+                    let voice = InputFile::memory(ogg_bytes).file_name(format!("response-{}.ogg", i + 1));
+                    let mut request = bot.send_voice(msg.chat.id, voice);
+                    if count > 1 {
+                        request = request.caption(format!("🔊 Part {}/{}", i + 1, count));
                     }
+                    request.await?;
                 }
             }
+            Ok(_) => send_long_message(bot, msg.chat.id, &assistant_text).await?,
             Err(e) => {
-                tracing::error!("TTS failed: {}", e);
+                tracing::error!("{:#}", e);
                 // Fallback to text
                 send_long_message(bot, msg.chat.id, &assistant_text).await?;
             }
         }
-
-        // If the response was truncated for TTS, also send full text
-        if assistant_text.len() > TTS_MAX_CHARS {
-            send_long_message(bot, msg.chat.id, &assistant_text).await?;
-        }
+    } else if let Some(reply) = stream_reply {
+        // Text was already streamed; make the final edit
+        reply.finish().await?;
     } else {
         // Reply with text (split if too long)
         send_long_message(bot, msg.chat.id, &assistant_text).await?;
     }
 
 
-    // ── 11. Periodically update user profile (every ~10 messages) ──
+    // ── 12. Periodically update user profile (every ~10 messages) ──
 
     let msg_count = state.db.get_messages(conv_id).await.map(|m: Vec<crate::db::models::Message>| m.len()).unwrap_or(0);
     if msg_count % 10 == 0 && msg_count > 0 {
         let db_clone = state.db.clone();
-        let llm_clone_config = state.config.clone();
-        let llm = crate::ai::llm::LlmClient::new(&llm_clone_config);
+        let llm = state.llm.clone();
         let ctx = ContextManager::new(state.config.max_context_tokens);
         tokio::spawn(async move {
             if let Err(e) = ctx.maybe_update_profile(&db_clone, &llm, user_id, conv_id).await {
@@ -293,12 +465,167 @@ async fn handle_message_inner(
     Ok(())
 }
 
+/// Run one streaming LLM call, rendering text deltas into `reply` as they arrive.
+async fn stream_completion(
+    state: &Arc<AppState>,
+    reply: &mut StreamingReply<'_>,
+    messages: &[ChatMessage],
+    model: &str,
+    tools: &[ToolDefinition],
+) -> anyhow::Result<LlmResponse> {
+    let mut stream = state
+        .llm
+        .chat_stream_with_model(messages, model, tools)
+        .await?;
+
+    while let Some(event) = stream.recv().await {
+        match event? {
+            StreamEvent::Delta(delta) => reply.push(&delta).await?,
+            StreamEvent::Done(response) => return Ok(response),
+        }
+    }
+
+    anyhow::bail!("LLM stream ended without a final response")
+}
+
+/// Download a Telegram photo and encode it as a base64 `data:` URL.
+async fn download_image_data_url(bot: &Bot, file_id: &str) -> anyhow::Result<String> {
+    let file = bot.get_file(file_id).await?;
+    let mut buf = Vec::new();
+    bot.download_file(&file.path, &mut buf).await?;
+
+    // Telegram re-encodes photos as JPEG
+    Ok(format!("data:image/jpeg;base64,{}", BASE64.encode(&buf)))
+}
+
+/// Convert stored messages into the LLM history, attaching loaded `images`.
+/// Drops tool calls and tool results that lost their counterpart (e.g. after pruning),
+/// since the API rejects unmatched pairs.
+fn history_to_chat_messages(
+    db_messages: &[crate::db::models::Message],
+    images: &HashMap<Uuid, String>,
+) -> Vec<ChatMessage> {
+    let answered: HashSet<&str> = db_messages
+        .iter()
+        .filter(|m| m.role == "tool")
+        .filter_map(|m| m.tool_call_id.as_deref())
+        .collect();
+
+    let mut announced: HashSet<String> = HashSet::new();
+    let mut out = Vec::with_capacity(db_messages.len());
+
+    for m in db_messages {
+        if m.role == "tool" {
+            match m.tool_call_id.as_deref() {
+                Some(id) if announced.contains(id) => {
+                    out.push(ChatMessage {
+                        role: "tool".to_string(),
+                        content: m.content.clone(),
+                        tool_call_id: Some(id.to_string()),
+                        ..Default::default()
+                    });
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let tool_calls: Vec<ToolCall> = m
+            .tool_calls
+            .as_ref()
+            .and_then(|v| serde_json::from_value::<Vec<ToolCall>>(v.clone()).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|c| answered.contains(c.id.as_str()))
+            .collect();
+
+        if m.content.is_empty() && tool_calls.is_empty() {
+            continue;
+        }
+
+        announced.extend(tool_calls.iter().map(|c| c.id.clone()));
+        out.push(ChatMessage {
+            role: m.role.clone(),
+            content: m.content.clone(),
+            tool_calls,
+            images: images.get(&m.id).cloned().into_iter().collect(),
+            ..Default::default()
+        });
+    }
+
+    out
+}
+
+/// Run a single tool call requested by the LLM and return its result for the model.
+async fn execute_tool_call(
+    bot: &Bot,
+    state: &Arc<AppState>,
+    identity_mgr: &IdentityManager,
+    tool_call: &ToolCall,
+    user_id: i64,
+    chat_id: i64,
+) -> anyhow::Result<String> {
+    match tool_call.name.as_str() {
+        "run_command" => {
+            let Some(cmd) = tool_call.arguments.get("command").and_then(|v| v.as_str()) else {
+                return Ok("❌ run_command requires a 'command' argument.".to_string());
+            };
+
+            match CommandExecutor::execute(&state.db, cmd, user_id, chat_id).await? {
+                ExecutionResult::Immediate(output) => Ok(if output.is_empty() {
+                    "(no output)".to_string()
+                } else {
+                    output
+                }),
+                ExecutionResult::PendingApproval(approval_id) => {
+                    // Send to admin group
+                    crate::agent::approval::request_approval(
+                        bot,
+                        state.config.admin_group_id,
+                        cmd,
+                        user_id,
+                        approval_id,
+                    )
+                    .await?;
+                    Ok(format!(
+                        "Pending: the command needs admin approval (request {}). \
+                         The user will be notified of the result.",
+                        approval_id
+                    ))
+                }
+                ExecutionResult::Blocked => {
+                    Ok("Blocked: the command is not allowed for safety reasons.".to_string())
+                }
+            }
+        }
+
+        "update_persona" => {
+            if !state.config.is_admin(user_id) {
+                return Ok("❌ Only admins can update persona files.".to_string());
+            }
+
+            let (Some(file_name), Some(new_content)) = (
+                tool_call.arguments.get("file_name").and_then(|v| v.as_str()),
+                tool_call.arguments.get("new_content").and_then(|v| v.as_str()),
+            ) else {
+                return Ok(
+                    "❌ update_persona requires 'file_name' and 'new_content' arguments."
+                        .to_string(),
+                );
+            };
+
+            identity_mgr.update_file(file_name, new_content).await?;
+            Ok(format!("✅ Updated persona file: {}.md", file_name))
+        }
+
+        _ => Ok(format!("Tool '{}' is not implemented yet.", tool_call.name)),
+    }
+}
+
 /// Send a message that may exceed Telegram's 4096 character limit
 /// by splitting it into multiple messages.
 async fn send_long_message(bot: &Bot, chat_id: ChatId, text: &str) -> anyhow::Result<()> {
-    const MAX_LEN: usize = 4096;
-
-    if text.len() <= MAX_LEN {
+    if text.len() <= MAX_MESSAGE_LEN {
         bot.send_message(chat_id, text).await?;
         return Ok(());
     }
@@ -306,16 +633,13 @@ async fn send_long_message(bot: &Bot, chat_id: ChatId, text: &str) -> anyhow::Re
     // Split into chunks, preferring to break at newlines
     let mut remaining = text;
     while !remaining.is_empty() {
-        if remaining.len() <= MAX_LEN {
+        if remaining.len() <= MAX_MESSAGE_LEN {
             bot.send_message(chat_id, remaining).await?;
             break;
         }
 
         // Find a good split point (last newline before limit, or last space)
-        let chunk_end = remaining[..MAX_LEN]
-            .rfind('\n')
-            .or_else(|| remaining[..MAX_LEN].rfind(' '))
-            .unwrap_or(MAX_LEN);
+        let chunk_end = split_point(remaining, MAX_MESSAGE_LEN);
 
         let (chunk, rest) = remaining.split_at(chunk_end);
         bot.send_message(chat_id, chunk).await?;
@@ -326,76 +650,3 @@ async fn send_long_message(bot: &Bot, chat_id: ChatId, text: &str) -> anyhow::Re
 
     Ok(())
 }
-
-/// Convert OGG/Opus audio to PCM f32 16kHz mono using ffmpeg.
-async fn ogg_to_pcm(ogg_data: &[u8]) -> anyhow::Result<Vec<f32>> {
-    use tokio::process::Command;
-    use std::process::Stdio;
-    use tokio::io::AsyncWriteExt;
-
-    let mut child = Command::new("ffmpeg")
-        .args([
-            "-i", "pipe:0",
-            "-f", "f32le",
-            "-acodec", "pcm_f32le",
-            "-ar", "16000",
-            "-ac", "1",
-            "pipe:1",
-        ])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()?;
-
-    if let Some(mut stdin) = child.stdin.take() {
-        stdin.write_all(ogg_data).await?;
-        drop(stdin);
-    }
-
-    let output = child.wait_with_output().await?;
-
-    if !output.status.success() {
-        anyhow::bail!("ffmpeg ogg-to-pcm conversion failed");
-    }
-
-    // Convert raw bytes to f32 samples
-    let samples: Vec<f32> = output
-        .stdout
-        .chunks_exact(4)
-        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
-        .collect();
-
-    Ok(samples)
-}
-
-/// Convert WAV to OGG/Opus for Telegram voice messages using ffmpeg.
-async fn wav_to_ogg(wav_data: &[u8]) -> anyhow::Result<Vec<u8>> {
-    use tokio::process::Command;
-    use std::process::Stdio;
-    use tokio::io::AsyncWriteExt;
-
-    let mut child = Command::new("ffmpeg")
-        .args([
-            "-i", "pipe:0",
-            "-acodec", "libopus",
-            "-f", "ogg",
-            "pipe:1",
-        ])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()?;
-
-    if let Some(mut stdin) = child.stdin.take() {
-        stdin.write_all(wav_data).await?;
-        drop(stdin);
-    }
-
-    let output = child.wait_with_output().await?;
-
-    if !output.status.success() {
-        anyhow::bail!("ffmpeg wav-to-ogg conversion failed");
-    }
-
-    Ok(output.stdout)
-}
@@ -0,0 +1,487 @@
+use teloxide::net::Download;
+use teloxide::prelude::*;
+use teloxide::types::{FileMeta, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, MessageId};
+use uuid::Uuid;
+
+use crate::ai::chunking;
+use crate::ai::stt::{self, TranscribeOptions, Transcription, TranscriptionCancelled};
+use crate::ai::subtitles::{self, TranscriptFormat};
+use crate::ai::vad::{self, VadConfig, VadRejection};
+use crate::ai::whisper_models::ModelTier;
+use crate::audio::decode::NoAudioTrack;
+use crate::audio::{self, denoise, resample, wav};
+use crate::bot::streaming::MAX_MESSAGE_LEN;
+use crate::bot::AppState;
+use crate::config::AppConfig;
+
+/// Media longer than this gets progress messages while it is processed.
+const LONG_MEDIA_SECS: u32 = 60;
+
+/// Denoise debug clips stop after this many seconds of the recording.
+const DENOISE_DEBUG_MAX_SECS: usize = 60;
+
+/// File extensions accepted for audio sent as a document (Telegram often
+/// reports these as `application/octet-stream`).
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "aac", "ogg", "oga", "opus", "wav", "flac", "wma", "amr"];
+
+/// An attachment whose audio track can be transcribed.
+pub struct AudioSource<'a> {
+    pub file: &'a FileMeta,
+    /// Duration in seconds, when Telegram reports it.
+    pub duration: Option<u32>,
+    /// What the user sent, for messages ("voice message", "video note", ...).
+    pub kind: &'static str,
+}
+
+impl AudioSource<'_> {
+    fn is_long(&self) -> bool {
+        self.duration.is_some_and(|d| d >= LONG_MEDIA_SECS)
+    }
+}
+
+/// The user's STT language setting, or the configured default.
+pub fn stt_language(settings: &serde_json::Value, config: &AppConfig) -> String {
+    settings
+        .get("stt_language")
+        .and_then(|v| v.as_str())
+        .unwrap_or(&config.default_stt_language)
+        .to_string()
+}
+
+/// The user's whisper tier from /settings, or the configured default.
+pub fn stt_tier(settings: &serde_json::Value, config: &AppConfig) -> ModelTier {
+    settings
+        .get("stt_tier")
+        .and_then(|v| v.as_str())
+        .and_then(ModelTier::from_str_loose)
+        .or_else(|| ModelTier::from_str_loose(&config.stt_model_tier))
+        .unwrap_or(ModelTier::Base)
+}
+
+/// Whether to denoise the user's audio: their /settings toggle, or the configured default.
+pub fn denoise_enabled(settings: &serde_json::Value, config: &AppConfig) -> bool {
+    settings
+        .get("denoise")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(config.denoise_enabled)
+}
+
+/// Admins can have the audio sent back before and after denoising.
+pub fn denoise_debug(settings: &serde_json::Value, user_id: i64, config: &AppConfig) -> bool {
+    config.is_admin(user_id)
+        && settings
+            .get("denoise_debug")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+}
+
+/// The user's /vocab terms.
+pub fn user_vocabulary(settings: &serde_json::Value) -> Vec<String> {
+    settings
+        .get("vocabulary")
+        .and_then(|v| v.as_array())
+        .map(|terms| {
+            terms
+                .iter()
+                .filter_map(|t| t.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whisper's initial prompt: the global glossary followed by the user's own terms.
+async fn vocabulary_prompt(state: &AppState, settings: &serde_json::Value) -> Option<String> {
+    let global = state.global_vocabulary.read().await;
+    let user = user_vocabulary(settings);
+    stt::vocabulary_prompt(global.iter().chain(&user).map(String::as_str))
+}
+
+/// The user's preferred transcript format from /settings.
+pub fn transcript_format(settings: &serde_json::Value) -> TranscriptFormat {
+    settings
+        .get("transcript_format")
+        .and_then(|v| v.as_str())
+        .map(TranscriptFormat::from_str_loose)
+        .unwrap_or(TranscriptFormat::Text)
+}
+
+/// If the next audio should only be transcribed, the format to deliver it in.
+/// Applies in the "transcribe" response mode, or once after /transcribe armed it
+/// (`transcribe_next` holds a format, or `true` for the preferred one).
+pub fn transcript_request(settings: &serde_json::Value) -> Option<TranscriptFormat> {
+    match settings.get("transcribe_next") {
+        Some(serde_json::Value::String(format)) => {
+            return Some(TranscriptFormat::from_str_loose(format))
+        }
+        Some(serde_json::Value::Bool(true)) => return Some(transcript_format(settings)),
+        _ => {}
+    }
+
+    (settings.get("response_mode").and_then(|v| v.as_str()) == Some("transcribe"))
+        .then(|| transcript_format(settings))
+}
+
+/// Find a transcribable attachment: voice, audio, video note, video, or an audio document.
+pub fn audio_source(msg: &Message) -> Option<AudioSource<'_>> {
+    if let Some(voice) = msg.voice() {
+        return Some(AudioSource {
+            file: &voice.file,
+            duration: Some(voice.duration.seconds()),
+            kind: "voice message",
+        });
+    }
+    if let Some(audio) = msg.audio() {
+        return Some(AudioSource {
+            file: &audio.file,
+            duration: Some(audio.duration.seconds()),
+            kind: "audio file",
+        });
+    }
+    if let Some(note) = msg.video_note() {
+        return Some(AudioSource {
+            file: &note.file,
+            duration: Some(note.duration.seconds()),
+            kind: "video note",
+        });
+    }
+    if let Some(video) = msg.video() {
+        return Some(AudioSource {
+            file: &video.file,
+            duration: Some(video.duration.seconds()),
+            kind: "video",
+        });
+    }
+    if let Some(doc) = msg.document() {
+        let audio_mime = doc
+            .mime_type
+            .as_ref()
+            .is_some_and(|m| m.type_().as_str() == "audio");
+        let audio_ext = doc
+            .file_name
+            .as_deref()
+            .and_then(|name| name.rsplit_once('.'))
+            .is_some_and(|(_, ext)| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+
+        if audio_mime || audio_ext {
+            return Some(AudioSource {
+                file: &doc.file,
+                duration: None,
+                kind: "audio file",
+            });
+        }
+    }
+    None
+}
+
+/// Download, decode and transcribe an attachment on the transcription pool.
+/// Language and vocabulary come from the user's `settings`. Long files and a busy
+/// queue get a status message that tracks progress and lets `user_id` cancel the job.
+/// Returns `None` if the clip was refused or had no speech (the user has been told).
+pub async fn transcribe_source(
+    bot: &Bot,
+    state: &AppState,
+    chat_id: ChatId,
+    user_id: i64,
+    source: &AudioSource<'_>,
+    settings: &serde_json::Value,
+) -> anyhow::Result<Option<Transcription>> {
+    let max_bytes = state.config.max_media_mb * 1024 * 1024;
+    if source.file.size as u64 > max_bytes {
+        bot.send_message(
+            chat_id,
+            format!(
+                "📦 That {} is too large ({:.1} MB). The limit is {} MB.",
+                source.kind,
+                source.file.size as f64 / (1024.0 * 1024.0),
+                state.config.max_media_mb
+            ),
+        )
+        .await?;
+        return Ok(None);
+    }
+
+    let mut status = Status::new(bot, chat_id);
+    if source.is_long() {
+        status.set(&format!("📥 Downloading {}…", source.kind)).await;
+    } else {
+        bot.send_chat_action(chat_id, teloxide::types::ChatAction::Typing)
+            .await?;
+    }
+
+    let file = bot.get_file(&source.file.id).await?;
+    let mut buf = Vec::new();
+    bot.download_file(&file.path, &mut buf).await?;
+
+    let decoded = match audio::decode_audio(buf, state.config.ffmpeg_fallback).await {
+        Ok(decoded) if !decoded.samples.is_empty() => Ok(decoded),
+        Ok(_) => Err(NoAudioTrack.into()),
+        Err(e) => Err(e),
+    };
+    let decoded = match decoded {
+        Ok(decoded) => decoded,
+        Err(e) if e.is::<NoAudioTrack>() => {
+            tracing::info!("No audio track in {}: {:#}", source.kind, e);
+            status.clear().await;
+            bot.send_message(chat_id, format!("🔇 I couldn't find an audio track in that {}.", source.kind))
+                .await?;
+            return Ok(None);
+        }
+        // A file we can't read or a broken decoder is an error, not a silent clip
+        Err(e) => {
+            status.clear().await;
+            return Err(e.context(format!("Could not decode that {}", source.kind)));
+        }
+    };
+
+    // RNNoise runs at 48 kHz, so it gets the audio before it is brought down to 16 kHz
+    let max_attenuation_db = denoise_enabled(settings, &state.config)
+        .then_some(state.config.denoise_max_attenuation_db);
+    let (mut pcm, denoised) = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        let pcm = resample::resample(&decoded.samples, decoded.sample_rate, audio::WHISPER_SAMPLE_RATE)?;
+        let denoised = max_attenuation_db.map(|db| {
+            denoise::denoise(&decoded.samples, decoded.sample_rate, db)
+                .and_then(|clean| resample::resample(&clean, denoise::SAMPLE_RATE, audio::WHISPER_SAMPLE_RATE))
+        });
+        Ok((pcm, denoised))
+    })
+    .await??;
+
+    match denoised {
+        Some(Ok(denoised)) => {
+            if denoise_debug(settings, user_id, &state.config) {
+                if let Err(e) = send_denoise_debug(bot, state, chat_id, &pcm, &denoised).await {
+                    tracing::warn!("Could not send denoise debug audio: {}", e);
+                }
+            }
+            pcm = denoised;
+        }
+        Some(Err(e)) => tracing::warn!("Denoising failed, transcribing the original audio: {}", e),
+        None => {}
+    }
+
+    let regions = if state.config.vad_enabled {
+        let vad_config = VadConfig {
+            threshold_db: state.config.vad_threshold_db,
+            max_chunk_secs: state.config.vad_max_chunk_secs,
+        };
+        match vad::detect(&pcm, &vad_config) {
+            Ok(regions) => regions,
+            Err(reason) => {
+                status.clear().await;
+                tracing::info!("Skipping {}: {}", source.kind, reason);
+                bot.send_message(chat_id, vad_rejection_message(reason, source.kind))
+                    .await?;
+                return Ok(None);
+            }
+        }
+    } else {
+        let whole_clip = 0..pcm.len();
+        vec![whole_clip]
+    };
+
+    let seconds = regions.iter().map(|r| r.len()).sum::<usize>() / 16_000;
+    let windows = chunking::plan_windows(
+        &regions,
+        state.config.stt_window_secs.max(1) as usize * 16_000,
+        state.config.stt_window_overlap_secs as usize * 16_000,
+    );
+
+    // Whisper runs on the transcription pool; tell the user if they have to wait
+    let options = TranscribeOptions {
+        language: stt_language(settings, &state.config),
+        prompt: vocabulary_prompt(state, settings).await,
+        tier: stt_tier(settings, &state.config),
+    };
+    let Some(job) = state.stt.submit(pcm, windows, options, user_id) else {
+        status.clear().await;
+        bot.send_message(
+            chat_id,
+            "🚦 Too many recordings are being transcribed right now. Please try again shortly.",
+        )
+        .await?;
+        return Ok(None);
+    };
+
+    // Long or queued jobs get a progress message with a cancel button
+    let total = job.total;
+    let show_progress =
+        source.is_long() || seconds >= LONG_MEDIA_SECS as usize || total > 1 || job.position > 0;
+    if show_progress {
+        status.cancellable(job.id);
+        if job.position > 0 {
+            status
+                .set(&format!("⏳ Transcription queue is busy — you're #{} in line.", job.position))
+                .await;
+        } else {
+            status.set(&transcribing_text(seconds, 0, total)).await;
+        }
+    }
+
+    let mut progress = job.progress.clone();
+    let wait = job.wait();
+    tokio::pin!(wait);
+    let transcription = loop {
+        tokio::select! {
+            biased;
+            result = &mut wait => break result,
+            Ok(()) = progress.changed(), if show_progress => {
+                let done = *progress.borrow_and_update();
+                status.set(&transcribing_text(seconds, done, total)).await;
+            }
+        }
+    };
+
+    let transcription = match transcription {
+        Err(e) if e.is::<TranscriptionCancelled>() => {
+            status.finish("❌ Transcription cancelled.").await;
+            return Ok(None);
+        }
+        other => {
+            status.clear().await;
+            other?
+        }
+    };
+
+    if transcription.text.is_empty() {
+        bot.send_message(chat_id, format!("🤔 I couldn't understand that {}.", source.kind))
+            .await?;
+        return Ok(None);
+    }
+
+    Ok(Some(transcription))
+}
+
+/// Progress line for a transcription split into `total` windows.
+fn transcribing_text(seconds: usize, done: usize, total: usize) -> String {
+    let mut text = format!("🎧 Transcribing {}:{:02} of audio…", seconds / 60, seconds % 60);
+    if total > 1 {
+        text.push_str(&format!(" {}/{} ({}%)", done, total, done * 100 / total));
+    }
+    text
+}
+
+/// What to tell the user when VAD turns a clip away.
+fn vad_rejection_message(reason: VadRejection, kind: &str) -> String {
+    match reason {
+        VadRejection::TooShort => format!("⏱ That {} is too short to transcribe.", kind),
+        VadRejection::NoSpeech => format!("🔇 I couldn't hear any speech in that {}.", kind),
+    }
+}
+
+/// Send the audio whisper would have heard without and with denoising, as voice
+/// notes cut to the first [`DENOISE_DEBUG_MAX_SECS`].
+async fn send_denoise_debug(
+    bot: &Bot,
+    state: &AppState,
+    chat_id: ChatId,
+    original: &[f32],
+    denoised: &[f32],
+) -> anyhow::Result<()> {
+    let max_len = DENOISE_DEBUG_MAX_SECS * audio::WHISPER_SAMPLE_RATE as usize;
+    for (label, pcm) in [("before", original), ("after", denoised)] {
+        let pcm = &pcm[..pcm.len().min(max_len)];
+        let wav = wav::f32_to_wav(pcm, audio::WHISPER_SAMPLE_RATE);
+        let ogg = audio::wav_to_voice(wav, state.config.ffmpeg_fallback).await?;
+        let voice = InputFile::memory(ogg).file_name(format!("denoise_{}.ogg", label));
+        bot.send_voice(chat_id, voice)
+            .caption(format!("🐞 Denoise debug: {}", label))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Reply with a bare transcript. Text goes out as a message, or as a .txt file
+/// when too long for one; subtitle formats are always sent as a file.
+pub async fn send_transcript(
+    bot: &Bot,
+    chat_id: ChatId,
+    transcription: &Transcription,
+    format: TranscriptFormat,
+) -> anyhow::Result<()> {
+    let header = format!(
+        "📝 Transcript [{}, {:.0}% confidence]",
+        transcription.language,
+        transcription.confidence() * 100.0
+    );
+
+    let body = match format {
+        TranscriptFormat::Text => {
+            if header.len() + 2 + transcription.text.len() <= MAX_MESSAGE_LEN {
+                bot.send_message(chat_id, format!("{}\n\n{}", header, transcription.text))
+                    .await?;
+                return Ok(());
+            }
+            transcription.text.clone()
+        }
+        TranscriptFormat::Srt => subtitles::to_srt(&transcription.segments),
+        TranscriptFormat::Vtt => subtitles::to_vtt(&transcription.segments),
+    };
+
+    let file = InputFile::memory(body.into_bytes())
+        .file_name(format!("transcript.{}", format.as_str()));
+    bot.send_document(chat_id, file).caption(header).await?;
+
+    Ok(())
+}
+
+/// A single status message that is sent once, then edited, then deleted.
+/// Failures are ignored: status updates are best-effort.
+struct Status<'a> {
+    bot: &'a Bot,
+    chat_id: ChatId,
+    message_id: Option<MessageId>,
+    /// Shown under the message on every update.
+    keyboard: Option<InlineKeyboardMarkup>,
+}
+
+impl<'a> Status<'a> {
+    fn new(bot: &'a Bot, chat_id: ChatId) -> Self {
+        Self {
+            bot,
+            chat_id,
+            message_id: None,
+            keyboard: None,
+        }
+    }
+
+    /// Attach a button that cancels transcription job `job_id`.
+    fn cancellable(&mut self, job_id: Uuid) {
+        self.keyboard = Some(InlineKeyboardMarkup::new(vec![vec![
+            InlineKeyboardButton::callback("❌ Cancel", format!("cancel_stt:{}", job_id)),
+        ]]));
+    }
+
+    async fn set(&mut self, text: &str) {
+        match self.message_id {
+            Some(id) => {
+                let mut edit = self.bot.edit_message_text(self.chat_id, id, text);
+                if let Some(keyboard) = &self.keyboard {
+                    edit = edit.reply_markup(keyboard.clone());
+                }
+                let _ = edit.await;
+            }
+            None => {
+                let mut send = self.bot.send_message(self.chat_id, text);
+                if let Some(keyboard) = &self.keyboard {
+                    send = send.reply_markup(keyboard.clone());
+                }
+                if let Ok(sent) = send.await {
+                    self.message_id = Some(sent.id);
+                }
+            }
+        }
+    }
+
+    /// Leave `text` in place of the status, without buttons.
+    async fn finish(&mut self, text: &str) {
+        self.keyboard = None;
+        self.set(text).await;
+        self.message_id = None;
+    }
+
+    async fn clear(&mut self) {
+        if let Some(id) = self.message_id.take() {
+            let _ = self.bot.delete_message(self.chat_id, id).await;
+        }
+    }
+}
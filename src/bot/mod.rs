@@ -1,25 +1,37 @@
 pub mod callbacks;
 pub mod commands;
 pub mod handlers;
+pub mod media;
+pub mod streaming;
+pub mod voices;
 
 use std::sync::Arc;
 use teloxide::dispatching::UpdateFilterExt;
 use teloxide::dptree;
 use teloxide::prelude::*;
 
-use crate::ai::{llm::LlmClient, stt::SttEngine, tts::TtsManager};
+use crate::ai::{
+    llm::LlmClient, stt_pool::TranscriptionPool, tts::TtsRegistry, whisper_models::ModelRegistry,
+};
 use crate::config::AppConfig;
 use crate::db::Database;
 
+/// `bot_settings` key of the global STT vocabulary.
+pub const GLOBAL_VOCABULARY_KEY: &str = "global_vocabulary";
+
 /// Shared application state, accessible from all handlers.
 pub struct AppState {
     pub config: AppConfig,
     pub db: Database,
-    pub stt: SttEngine,
-    pub tts: TtsManager,
+    pub stt: TranscriptionPool,
+    /// Whisper models on disk, for tier choices in /settings
+    pub stt_models: Arc<ModelRegistry>,
+    pub tts: TtsRegistry,
     pub llm: LlmClient,
     /// Runtime model override (admin can change via /model command)
     pub model_override: tokio::sync::RwLock<String>,
+    /// Glossary every transcription is primed with (admin can edit via /vocab global)
+    pub global_vocabulary: tokio::sync::RwLock<Vec<String>>,
 }
 
 /// Build the teloxide update handler tree.
@@ -0,0 +1,139 @@
+use std::time::{Duration, Instant};
+use teloxide::prelude::*;
+use teloxide::types::MessageId;
+
+/// Telegram's maximum message length.
+pub const MAX_MESSAGE_LEN: usize = 4096;
+
+/// Minimum time between edits of the same message (Telegram rate-limits edits).
+const EDIT_INTERVAL: Duration = Duration::from_millis(1200);
+
+/// Text shown before the first delta arrives.
+const PLACEHOLDER: &str = "✍️ …";
+
+/// Renders a streamed LLM reply into Telegram by progressively editing a
+/// placeholder message. Spills into additional messages past 4096 characters,
+/// and each agent step that says something gets messages of its own.
+pub struct StreamingReply<'a> {
+    bot: &'a Bot,
+    chat_id: ChatId,
+    /// The message currently being edited; `None` after a step ended, until the
+    /// next one has text to show.
+    message_id: Option<MessageId>,
+    /// Full text that belongs to the current message.
+    text: String,
+    /// What Telegram currently shows, to skip no-op edits.
+    shown: String,
+    last_edit: Instant,
+}
+
+impl<'a> StreamingReply<'a> {
+    /// Send the placeholder message that will be edited as deltas arrive.
+    pub async fn start(bot: &'a Bot, chat_id: ChatId) -> anyhow::Result<Self> {
+        let placeholder = bot.send_message(chat_id, PLACEHOLDER).await?;
+        Ok(Self {
+            bot,
+            chat_id,
+            message_id: Some(placeholder.id),
+            text: String::new(),
+            shown: PLACEHOLDER.to_string(),
+            last_edit: Instant::now(),
+        })
+    }
+
+    /// Append a delta, editing the message if the throttle interval has passed.
+    pub async fn push(&mut self, delta: &str) -> anyhow::Result<()> {
+        self.text.push_str(delta);
+
+        // Overflow: finalize the current message and continue in a new one
+        while self.text.len() > MAX_MESSAGE_LEN {
+            let split = split_point(&self.text, MAX_MESSAGE_LEN);
+            let rest = self.text.split_off(split);
+            self.flush().await?;
+
+            let rest = rest.trim_start().to_string();
+            let next = self
+                .bot
+                .send_message(self.chat_id, if rest.is_empty() { PLACEHOLDER } else { &rest })
+                .await?;
+            self.message_id = Some(next.id);
+            self.shown = if rest.is_empty() { PLACEHOLDER.to_string() } else { rest.clone() };
+            self.text = rest;
+            self.last_edit = Instant::now();
+        }
+
+        if self.last_edit.elapsed() >= EDIT_INTERVAL {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    /// End an agent step that called tools. Its text stays in its own message,
+    /// matching what is saved for the step, and the next step starts a new one.
+    pub async fn next_step(&mut self) -> anyhow::Result<()> {
+        if self.text.trim().is_empty() {
+            // Nothing said yet; the placeholder carries over
+            return Ok(());
+        }
+        self.flush().await?;
+        self.message_id = None;
+        self.text.clear();
+        self.shown.clear();
+        Ok(())
+    }
+
+    /// Make the final edit once the stream has ended, or after it failed.
+    /// Removes the placeholder if nothing was ever streamed.
+    pub async fn finish(mut self) -> anyhow::Result<()> {
+        if self.text.trim().is_empty() && self.shown == PLACEHOLDER {
+            if let Some(message_id) = self.message_id {
+                let _ = self.bot.delete_message(self.chat_id, message_id).await;
+            }
+            return Ok(());
+        }
+        self.flush().await
+    }
+
+    /// Push the current text to Telegram if it changed since the last edit.
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        if self.text.trim().is_empty() || self.text == self.shown {
+            return Ok(());
+        }
+
+        match self.message_id {
+            Some(message_id) => {
+                self.bot
+                    .edit_message_text(self.chat_id, message_id, &self.text)
+                    .await?;
+            }
+            None => {
+                let sent = self.bot.send_message(self.chat_id, &self.text).await?;
+                self.message_id = Some(sent.id);
+            }
+        }
+        self.shown = self.text.clone();
+        self.last_edit = Instant::now();
+        Ok(())
+    }
+}
+
+/// Find a byte index at most `max_len` to split `text` at, preferring the last
+/// newline, then the last space, and always landing on a char boundary.
+pub fn split_point(text: &str, max_len: usize) -> usize {
+    if text.len() <= max_len {
+        return text.len();
+    }
+
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    let window = &text[..end];
+    window
+        .rfind('\n')
+        .or_else(|| window.rfind(' '))
+        .filter(|&i| i > 0)
+        .unwrap_or(end)
+}
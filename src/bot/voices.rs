@@ -0,0 +1,298 @@
+//! The /voice picker: languages, then voices, then speakers of multi-speaker voices.
+
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::ai::tts::VoiceInfo;
+use crate::bot::AppState;
+
+/// Speaker buttons per page (three per row).
+const SPEAKERS_PER_PAGE: usize = 24;
+
+/// Text and keyboard for a step of the picker.
+pub type Menu = (String, InlineKeyboardMarkup);
+
+/// All installed voices, across engines. Buttons refer to voices and languages
+/// by their position here, since ids can outgrow Telegram's 64-byte callback data.
+fn all_voices(state: &AppState) -> impl Iterator<Item = &VoiceInfo> {
+    state
+        .tts
+        .backends()
+        .iter()
+        .flat_map(|b| b.capabilities().voices.iter())
+}
+
+/// Installed languages as (code, name, voice count), sorted by name.
+fn languages(state: &AppState) -> Vec<(&str, &str, usize)> {
+    let mut languages: Vec<(&str, &str, usize)> = Vec::new();
+    for voice in all_voices(state) {
+        match languages.iter_mut().find(|(code, _, _)| *code == voice.language) {
+            Some((_, _, count)) => *count += 1,
+            None => languages.push((&voice.language, &voice.language_name, 1)),
+        }
+    }
+    languages.sort_by(|a, b| a.1.cmp(b.1));
+    languages
+}
+
+/// Callback data opening the voices of `language`.
+fn language_callback(state: &AppState, language: &str) -> String {
+    let index = languages(state)
+        .iter()
+        .position(|(code, _, _)| *code == language)
+        .unwrap_or_default();
+    format!("voice_lang:{}", index)
+}
+
+/// "Voice: amy-medium, speaker 3 (p239)" line for the user's current choice.
+fn current_voice_line(state: &AppState, settings: &serde_json::Value) -> String {
+    let Some((_, voice)) = settings
+        .get("tts_voice")
+        .and_then(|v| v.as_str())
+        .and_then(|id| state.tts.find_voice(id))
+    else {
+        return "🗣 Voice: automatic (matches the reply language)".to_string();
+    };
+
+    let speaker = settings.get("tts_speaker").and_then(|v| v.as_i64());
+    match speaker.and_then(|id| voice.speakers.iter().find(|s| s.id == id)) {
+        Some(speaker) => format!("🗣 Voice: {}, speaker {} ({})", voice.id, speaker.id, speaker.name),
+        None => format!("🗣 Voice: {}", voice.id),
+    }
+}
+
+/// First step: one button per language.
+pub fn language_menu(state: &AppState, settings: &serde_json::Value) -> Menu {
+    let languages = languages(state);
+
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = languages
+        .iter()
+        .enumerate()
+        .collect::<Vec<_>>()
+        .chunks(2)
+        .map(|pair| {
+            pair.iter()
+                .map(|(i, (_, name, count))| {
+                    InlineKeyboardButton::callback(format!("{} ({})", name, count), format!("voice_lang:{}", i))
+                })
+                .collect()
+        })
+        .collect();
+    rows.push(vec![InlineKeyboardButton::callback("🔄 Automatic", "voice_auto")]);
+
+    let text = if languages.is_empty() {
+        "🗣 No selectable voices are installed.".to_string()
+    } else {
+        format!("{}\n\nPick a language:", current_voice_line(state, settings))
+    };
+    (text, InlineKeyboardMarkup::new(rows))
+}
+
+/// Second step: the voices of one language.
+fn voice_menu(state: &AppState, settings: &serde_json::Value, language: &str) -> Menu {
+    let current = settings.get("tts_voice").and_then(|v| v.as_str());
+
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = all_voices(state)
+        .enumerate()
+        .filter(|(_, v)| v.language == language)
+        .map(|(i, voice)| {
+            // "en_US-amy-medium" → "amy-medium"
+            let name = voice
+                .id
+                .strip_prefix(&voice.language)
+                .map(|n| n.trim_start_matches(['-', '_']))
+                .filter(|n| !n.is_empty())
+                .unwrap_or(&voice.id);
+            let mut label = format!("{} {}", if current == Some(voice.id.as_str()) { "✅" } else { "⬜" }, name);
+            if !voice.speakers.is_empty() {
+                label.push_str(&format!(" ({} speakers)", voice.speakers.len()));
+            }
+            vec![InlineKeyboardButton::callback(label, format!("voice_set:{}", i))]
+        })
+        .collect();
+    rows.push(vec![InlineKeyboardButton::callback("« Languages", "voice_langs")]);
+
+    (
+        format!("{}\n\nPick a voice:", current_voice_line(state, settings)),
+        InlineKeyboardMarkup::new(rows),
+    )
+}
+
+/// Third step, for multi-speaker voices: a page of speakers.
+fn speaker_menu(state: &AppState, settings: &serde_json::Value, voice: &VoiceInfo, page: usize) -> Menu {
+    let current = settings.get("tts_speaker").and_then(|v| v.as_i64());
+    let pages = voice.speakers.len().div_ceil(SPEAKERS_PER_PAGE).max(1);
+    let page = page.min(pages - 1);
+
+    let speakers = voice
+        .speakers
+        .iter()
+        .skip(page * SPEAKERS_PER_PAGE)
+        .take(SPEAKERS_PER_PAGE)
+        .collect::<Vec<_>>();
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = speakers
+        .chunks(3)
+        .map(|row| {
+            row.iter()
+                .map(|speaker| {
+                    InlineKeyboardButton::callback(
+                        format!(
+                            "{}{}",
+                            if current == Some(speaker.id) { "✅ " } else { "" },
+                            speaker.name
+                        ),
+                        format!("voice_spk:{}", speaker.id),
+                    )
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut nav = Vec::new();
+    if page > 0 {
+        nav.push(InlineKeyboardButton::callback("‹ Prev", format!("voice_spkpg:{}", page - 1)));
+    }
+    nav.push(InlineKeyboardButton::callback(
+        "« Voices",
+        language_callback(state, &voice.language),
+    ));
+    if page + 1 < pages {
+        nav.push(InlineKeyboardButton::callback("Next ›", format!("voice_spkpg:{}", page + 1)));
+    }
+    rows.push(nav);
+
+    (
+        format!(
+            "{}\n\nPick a speaker (page {}/{}), or send /voice {} <speaker>:",
+            current_voice_line(state, settings),
+            page + 1,
+            pages,
+            voice.id
+        ),
+        InlineKeyboardMarkup::new(rows),
+    )
+}
+
+/// `/voice <id> [speaker]`: pick a voice (and speaker, by id or name) directly.
+pub async fn set_voice_command(state: &AppState, user_id: i64, args: &str) -> anyhow::Result<String> {
+    let mut parts = args.split_whitespace();
+    let id = parts.next().unwrap_or_default();
+    let speaker_arg = parts.collect::<Vec<_>>().join(" ");
+
+    let Some((backend, voice)) = state.tts.find_voice(id) else {
+        return Ok(format!("❌ Unknown voice '{}'. Send /voice to browse.", id));
+    };
+
+    let speaker = if speaker_arg.is_empty() {
+        None
+    } else {
+        let found = voice.speakers.iter().find(|s| {
+            speaker_arg.parse::<i64>().is_ok_and(|n| n == s.id) || s.name.eq_ignore_ascii_case(&speaker_arg)
+        });
+        match found {
+            Some(speaker) => Some(speaker),
+            None => return Ok(format!("❌ {} has no speaker '{}'.", voice.id, speaker_arg)),
+        }
+    };
+
+    let mut settings = state.db.get_user_settings(user_id).await?;
+    settings["tts_engine"] = serde_json::json!(backend.id());
+    settings["tts_voice"] = serde_json::json!(voice.id);
+    settings["tts_speaker"] = serde_json::json!(speaker.map(|s| s.id));
+    state.db.update_user_settings(user_id, &settings).await?;
+
+    Ok(format!("✅ {}", current_voice_line(state, &settings).trim_start_matches("🗣 ")))
+}
+
+/// Handle a `voice_*` button. `action` is the callback data without the `voice_` prefix.
+pub async fn handle_callback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    state: &AppState,
+    user_id: i64,
+    action: &str,
+) -> anyhow::Result<()> {
+    let mut settings = state.db.get_user_settings(user_id).await?;
+    let mut notice = None;
+
+    let menu = if action == "langs" {
+        Some(language_menu(state, &settings))
+    } else if action == "auto" {
+        settings["tts_voice"] = serde_json::Value::Null;
+        settings["tts_speaker"] = serde_json::Value::Null;
+        state.db.update_user_settings(user_id, &settings).await?;
+        notice = Some("Voice: automatic".to_string());
+        Some(language_menu(state, &settings))
+    } else if let Some(index) = action.strip_prefix("lang:") {
+        let languages = languages(state);
+        match index.parse::<usize>().ok().and_then(|i| languages.get(i)) {
+            Some((code, _, _)) => Some(voice_menu(state, &settings, code)),
+            None => Some(language_menu(state, &settings)),
+        }
+    } else if let Some(index) = action.strip_prefix("set:") {
+        let voice = index.parse::<usize>().ok().and_then(|i| all_voices(state).nth(i));
+        match voice.and_then(|voice| state.tts.find_voice(&voice.id)) {
+            Some((backend, voice)) => {
+                settings["tts_engine"] = serde_json::json!(backend.id());
+                settings["tts_voice"] = serde_json::json!(voice.id);
+                settings["tts_speaker"] = serde_json::Value::Null;
+                state.db.update_user_settings(user_id, &settings).await?;
+                notice = Some(format!("Voice: {}", voice.id));
+
+                if voice.speakers.is_empty() {
+                    Some(voice_menu(state, &settings, &voice.language))
+                } else {
+                    Some(speaker_menu(state, &settings, voice, 0))
+                }
+            }
+            None => {
+                notice = Some("That voice is no longer installed".to_string());
+                Some(language_menu(state, &settings))
+            }
+        }
+    } else {
+        let voice = settings
+            .get("tts_voice")
+            .and_then(|v| v.as_str())
+            .and_then(|id| state.tts.find_voice(id))
+            .map(|(_, voice)| voice);
+
+        match (voice, action.split_once(':')) {
+            (Some(voice), Some(("spkpg", page))) => {
+                Some(speaker_menu(state, &settings, voice, page.parse().unwrap_or(0)))
+            }
+            (Some(voice), Some(("spk", speaker))) => {
+                match speaker.parse::<i64>().ok().and_then(|id| voice.speakers.iter().find(|s| s.id == id)) {
+                    Some(speaker) => {
+                        settings["tts_speaker"] = serde_json::json!(speaker.id);
+                        state.db.update_user_settings(user_id, &settings).await?;
+                        notice = Some(format!("Speaker: {}", speaker.name));
+                        let page = voice.speakers.iter().position(|s| s.id == speaker.id).unwrap_or(0)
+                            / SPEAKERS_PER_PAGE;
+                        Some(speaker_menu(state, &settings, voice, page))
+                    }
+                    None => None,
+                }
+            }
+            _ => None,
+        }
+    };
+
+    let mut answer = bot.answer_callback_query(&q.id);
+    if let Some(notice) = notice {
+        answer = answer.text(notice);
+    }
+    answer.await?;
+
+    if let (Some((text, keyboard)), Some(message)) = (menu, &q.message) {
+        if let Err(e) = bot
+            .edit_message_text(message.chat().id, message.id(), text)
+            .reply_markup(keyboard)
+            .await
+        {
+            tracing::warn!("Could not update the /voice menu: {}", e);
+        }
+    }
+
+    Ok(())
+}
@@ -1,10 +1,35 @@
 use serde::Deserialize;
 
+use crate::ai::whisper_models::ModelKey;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct AppConfig {
     pub telegram_bot_token: String,
-    pub groq_api_key: String,
+    /// Default chat model (`LLM_MODEL`, falling back to `GROQ_MODEL`)
     pub groq_model: String,
+    /// Vision-capable model used for turns whose history contains images
+    pub vision_model: String,
+
+    /// LLM backend preset: "groq", "openai" or "local" (any OpenAI-compatible server)
+    pub llm_provider: String,
+    /// Base URL of the OpenAI-compatible API, e.g. `http://localhost:8080/v1`
+    pub llm_base_url: String,
+    /// API key for the LLM backend (`LLM_API_KEY`, falling back to `GROQ_API_KEY`)
+    pub llm_api_key: String,
+    /// Header that carries the API key
+    pub llm_auth_header: String,
+    /// Prefix for the API key in the auth header ("Bearer"); empty sends the raw key
+    pub llm_auth_scheme: String,
+    /// JSON object of extra request fields merged into every completion request
+    pub llm_extra_params: serde_json::Value,
+    /// Comma-separated models to try, in order, when the primary model fails
+    pub llm_fallback_models: Vec<String>,
+    /// Retries per model on rate limits, 5xx and timeouts
+    pub llm_max_retries: u32,
+    /// Initial backoff delay in milliseconds (doubles per retry)
+    pub llm_retry_base_ms: u64,
+    /// Longest server-requested wait we honor before moving to the next model
+    pub llm_retry_max_wait_secs: u64,
     pub database_url: String,
 
     /// Comma-separated Telegram user IDs of admins
@@ -14,18 +39,98 @@ pub struct AppConfig {
 
     /// Default TTS engine: "piper" or "xtts"
     pub default_tts_engine: String,
+    /// TTS engines offered in /settings, in menu order
+    pub tts_engines: Vec<String>,
+    /// Longest piece of a reply synthesized in one TTS request, in characters
+    pub tts_chunk_chars: usize,
+    /// Longest voice note sent; longer replies are split into numbered notes
+    pub tts_max_voice_secs: u32,
     /// Path to the standalone piper binary
     pub piper_binary_path: String,
     /// Path to the directory containing piper shared libraries
     pub piper_lib_path: String,
+    /// Default Piper voice, spoken when the user hasn't picked one with /voice
     pub piper_model_path: String,
+    /// Directory scanned for Piper voices (`<voice>.onnx` + `<voice>.onnx.json`)
+    pub piper_voices_dir: String,
+    /// Longest one Piper utterance may take before its worker is restarted
+    pub piper_timeout_secs: u64,
+    /// Piper voices kept loaded at once; least recently used ones are stopped
+    pub piper_max_workers: usize,
+    /// Piper processes per voice, so chunks of a long reply are spoken in parallel
+    pub piper_processes_per_voice: usize,
+    /// How often dead Piper workers are restarted; also how long a worker idles before its probe
+    pub piper_health_check_secs: u64,
     pub xtts_sidecar_url: String,
 
-    /// Path to the GGML whisper model file
-    pub whisper_model_path: String,
+    /// Directory with GGML whisper models (`ggml-<tier>[.en][-<quant>].bin`)
+    pub whisper_models_dir: String,
+    /// Single-model setting from before the models directory; still used for its tier
+    pub whisper_model_path: Option<String>,
+    /// Default whisper tier for users who haven't picked one (tiny, base, small, medium)
+    pub stt_model_tier: String,
+    /// Memory budget for loaded whisper models; least recently used ones are unloaded
+    pub stt_models_max_mb: u64,
+    /// Where transcription runs: "local", "whisper_cpp" (server) or "openai" (compatible API)
+    pub stt_backend: String,
+    /// Remote STT server (whisper.cpp root, or OpenAI-style base URL ending in /v1)
+    pub stt_remote_url: String,
+    /// Bearer token for the remote STT server (empty for none)
+    pub stt_remote_api_key: String,
+    /// Model name sent to OpenAI-compatible STT servers
+    pub stt_remote_model: String,
+    /// Timeout for one remote transcription request
+    pub stt_remote_timeout_secs: u64,
+    /// Default speech language for users who haven't picked one ("auto" = detect)
+    pub default_stt_language: String,
+    /// Denoise voice input before transcription for users who haven't chosen in /settings
+    pub denoise_enabled: bool,
+    /// Most the denoiser may attenuate anything, in dB (higher is more aggressive)
+    pub denoise_max_attenuation_db: f32,
+    /// Hand formats the built-in decoders can't read to ffmpeg (must be installed)
+    pub ffmpeg_fallback: bool,
+    /// Largest audio/video attachment accepted for transcription, in MB
+    pub max_media_mb: u64,
+    /// Trim silence and skip speechless audio before whisper
+    pub vad_enabled: bool,
+    /// Frames quieter than this (dBFS) never count as speech
+    pub vad_threshold_db: f32,
+    /// Longest stretch handed to whisper at once; longer audio is split at pauses
+    pub vad_max_chunk_secs: u32,
+    /// Voice notes transcribed in parallel
+    pub stt_workers: usize,
+    /// CPU threads whisper uses per transcription
+    pub stt_threads: usize,
+    /// Whisper decoding strategy: "greedy" or "beam"
+    pub stt_sampling: String,
+    /// Beam width when decoding with beam search
+    pub stt_beam_size: i32,
+    /// Candidates compared per pass when decoding greedily
+    pub stt_best_of: i32,
+    /// Initial sampling temperature (0 = deterministic)
+    pub stt_temperature: f32,
+    /// Temperature step for whisper's fallback on low-quality passes (0 = off)
+    pub stt_temperature_inc: f32,
+    /// Names and terms every transcription is primed with (comma-separated in env)
+    pub stt_vocabulary: Vec<String>,
+    /// Voice notes allowed to wait for a worker before new ones are refused
+    pub stt_queue_size: usize,
+    /// Longest audio whisper hears in one pass; longer speech is split into windows
+    pub stt_window_secs: u32,
+    /// Audio shared by neighbouring windows so boundary words are heard whole
+    pub stt_window_overlap_secs: u32,
 
     /// Max tokens in conversation context before pruning
     pub max_context_tokens: usize,
+    /// Max LLM round-trips with tool calls per user message
+    pub max_agent_steps: usize,
+
+    /// Daily / monthly token quotas for regular users (0 = unlimited)
+    pub quota_user_daily: i64,
+    pub quota_user_monthly: i64,
+    /// Daily / monthly token quotas for admins (0 = unlimited)
+    pub quota_admin_daily: i64,
+    pub quota_admin_monthly: i64,
 }
 
 impl AppConfig {
@@ -36,11 +141,80 @@ impl AppConfig {
             .filter_map(|s| s.trim().parse().ok())
             .collect();
 
+        let llm_provider = std::env::var("LLM_PROVIDER")
+            .unwrap_or_else(|_| "groq".to_string())
+            .to_lowercase();
+        let default_base_url = match llm_provider.as_str() {
+            "openai" => "https://api.openai.com/v1",
+            "local" => "http://localhost:8080/v1",
+            _ => "https://api.groq.com/openai/v1",
+        };
+
+        let groq_api_key = std::env::var("GROQ_API_KEY").unwrap_or_default();
+        let llm_api_key = std::env::var("LLM_API_KEY").unwrap_or(groq_api_key);
+        // Hosted providers need a key; local servers usually don't
+        if llm_api_key.is_empty() && llm_provider != "local" {
+            anyhow::bail!("GROQ_API_KEY (or LLM_API_KEY) is required for provider '{}'", llm_provider);
+        }
+
+        let llm_extra_params = match std::env::var("LLM_EXTRA_PARAMS") {
+            Ok(raw) if !raw.trim().is_empty() => serde_json::from_str(&raw)
+                .map_err(|e| anyhow::anyhow!("LLM_EXTRA_PARAMS is not valid JSON: {}", e))?,
+            _ => serde_json::json!({}),
+        };
+        if !llm_extra_params.is_object() {
+            anyhow::bail!("LLM_EXTRA_PARAMS must be a JSON object");
+        }
+
+        // Older deployments point at a single model file instead of a directory
+        let whisper_model_path = std::env::var("WHISPER_MODEL_PATH")
+            .ok()
+            .filter(|p| !p.trim().is_empty());
+        let whisper_model_dir = whisper_model_path
+            .as_deref()
+            .and_then(|p| std::path::Path::new(p).parent())
+            .map(|d| d.to_string_lossy().into_owned())
+            .filter(|d| !d.is_empty());
+        let whisper_model_tier = whisper_model_path
+            .as_deref()
+            .and_then(|p| std::path::Path::new(p).file_name())
+            .and_then(|n| ModelKey::from_file_name(&n.to_string_lossy()))
+            .map(|key| key.tier.as_str().to_string());
+
         Ok(Self {
             telegram_bot_token: std::env::var("TELEGRAM_BOT_TOKEN")?,
-            groq_api_key: std::env::var("GROQ_API_KEY")?,
-            groq_model: std::env::var("GROQ_MODEL")
+            groq_model: std::env::var("LLM_MODEL")
+                .or_else(|_| std::env::var("GROQ_MODEL"))
                 .unwrap_or_else(|_| "llama-3.3-70b-versatile".to_string()),
+            vision_model: std::env::var("VISION_MODEL")
+                .unwrap_or_else(|_| "meta-llama/llama-4-scout-17b-16e-instruct".to_string()),
+            llm_base_url: std::env::var("LLM_BASE_URL")
+                .unwrap_or_else(|_| default_base_url.to_string()),
+            llm_api_key,
+            llm_auth_header: std::env::var("LLM_AUTH_HEADER")
+                .unwrap_or_else(|_| "Authorization".to_string()),
+            llm_auth_scheme: std::env::var("LLM_AUTH_SCHEME")
+                .unwrap_or_else(|_| "Bearer".to_string()),
+            llm_extra_params,
+            llm_fallback_models: std::env::var("LLM_FALLBACK_MODELS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            llm_max_retries: std::env::var("LLM_MAX_RETRIES")
+                .unwrap_or_else(|_| "3".to_string())
+                .parse()
+                .unwrap_or(3),
+            llm_retry_base_ms: std::env::var("LLM_RETRY_BASE_MS")
+                .unwrap_or_else(|_| "500".to_string())
+                .parse()
+                .unwrap_or(500),
+            llm_retry_max_wait_secs: std::env::var("LLM_RETRY_MAX_WAIT_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            llm_provider,
             database_url: std::env::var("DATABASE_URL")?,
             admin_ids,
             admin_group_id: std::env::var("ADMIN_GROUP_ID")
@@ -48,20 +222,167 @@ impl AppConfig {
                 .parse()?,
             default_tts_engine: std::env::var("DEFAULT_TTS_ENGINE")
                 .unwrap_or_else(|_| "piper".to_string()),
+            tts_engines: std::env::var("TTS_ENGINES")
+                .unwrap_or_else(|_| "piper,xtts".to_string())
+                .split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            tts_chunk_chars: std::env::var("TTS_CHUNK_CHARS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .unwrap_or(300),
+            tts_max_voice_secs: std::env::var("TTS_MAX_VOICE_SECS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .unwrap_or(300),
             piper_binary_path: std::env::var("PIPER_BINARY_PATH")
                 .unwrap_or_else(|_| "./data/piper/piper".to_string()),
             piper_lib_path: std::env::var("PIPER_LIB_PATH")
                 .unwrap_or_else(|_| "./data/piper".to_string()),
             piper_model_path: std::env::var("PIPER_MODEL_PATH")
                 .unwrap_or_else(|_| "./data/models/piper/en_US-amy-medium.onnx".to_string()),
+            piper_voices_dir: std::env::var("PIPER_VOICES_DIR")
+                .unwrap_or_else(|_| "./data/models/piper".to_string()),
+            piper_timeout_secs: std::env::var("PIPER_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            piper_max_workers: std::env::var("PIPER_MAX_WORKERS")
+                .unwrap_or_else(|_| "4".to_string())
+                .parse()
+                .unwrap_or(4),
+            piper_processes_per_voice: std::env::var("PIPER_PROCESSES_PER_VOICE")
+                .unwrap_or_else(|_| "2".to_string())
+                .parse()
+                .unwrap_or(2),
+            piper_health_check_secs: std::env::var("PIPER_HEALTH_CHECK_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
             xtts_sidecar_url: std::env::var("XTTS_SIDECAR_URL")
                 .unwrap_or_else(|_| "http://localhost:8020".to_string()),
-            whisper_model_path: std::env::var("WHISPER_MODEL_PATH")
-                .unwrap_or_else(|_| "./data/models/whisper/ggml-base.en.bin".to_string()),
+            whisper_models_dir: std::env::var("WHISPER_MODELS_DIR")
+                .ok()
+                .or(whisper_model_dir)
+                .unwrap_or_else(|| "./data/models/whisper".to_string()),
+            whisper_model_path,
+            stt_model_tier: std::env::var("STT_MODEL_TIER")
+                .ok()
+                .or(whisper_model_tier)
+                .unwrap_or_else(|| "base".to_string()),
+            stt_models_max_mb: std::env::var("STT_MODELS_MAX_MB")
+                .unwrap_or_else(|_| "2048".to_string())
+                .parse()
+                .unwrap_or(2048),
+            stt_backend: std::env::var("STT_BACKEND")
+                .unwrap_or_else(|_| "local".to_string()),
+            stt_remote_url: std::env::var("STT_REMOTE_URL").unwrap_or_default(),
+            stt_remote_api_key: std::env::var("STT_REMOTE_API_KEY").unwrap_or_default(),
+            stt_remote_model: std::env::var("STT_REMOTE_MODEL")
+                .unwrap_or_else(|_| "whisper-1".to_string()),
+            stt_remote_timeout_secs: std::env::var("STT_REMOTE_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "120".to_string())
+                .parse()
+                .unwrap_or(120),
+            default_stt_language: std::env::var("STT_LANGUAGE")
+                .unwrap_or_else(|_| "auto".to_string()),
+            denoise_enabled: std::env::var("DENOISE_ENABLED")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            denoise_max_attenuation_db: std::env::var("DENOISE_MAX_ATTENUATION_DB")
+                .unwrap_or_else(|_| "20".to_string())
+                .parse()
+                .unwrap_or(20.0),
+            ffmpeg_fallback: std::env::var("FFMPEG_FALLBACK")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .unwrap_or(true),
+            max_media_mb: std::env::var("MAX_MEDIA_MB")
+                .unwrap_or_else(|_| "20".to_string())
+                .parse()
+                .unwrap_or(20),
+            vad_enabled: std::env::var("VAD_ENABLED")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .unwrap_or(true),
+            vad_threshold_db: std::env::var("VAD_THRESHOLD_DB")
+                .unwrap_or_else(|_| "-50".to_string())
+                .parse()
+                .unwrap_or(-50.0),
+            vad_max_chunk_secs: std::env::var("VAD_MAX_CHUNK_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            stt_workers: std::env::var("STT_WORKERS")
+                .unwrap_or_else(|_| "1".to_string())
+                .parse()
+                .unwrap_or(1),
+            stt_threads: std::env::var("STT_THREADS")
+                .unwrap_or_else(|_| "2".to_string())
+                .parse()
+                .unwrap_or(2),
+            stt_sampling: std::env::var("STT_SAMPLING")
+                .unwrap_or_else(|_| "greedy".to_string()),
+            stt_beam_size: std::env::var("STT_BEAM_SIZE")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .unwrap_or(5),
+            stt_best_of: std::env::var("STT_BEST_OF")
+                .unwrap_or_else(|_| "1".to_string())
+                .parse()
+                .unwrap_or(1),
+            stt_temperature: std::env::var("STT_TEMPERATURE")
+                .unwrap_or_else(|_| "0.0".to_string())
+                .parse()
+                .unwrap_or(0.0),
+            stt_temperature_inc: std::env::var("STT_TEMPERATURE_INC")
+                .unwrap_or_else(|_| "0.2".to_string())
+                .parse()
+                .unwrap_or(0.2),
+            stt_vocabulary: std::env::var("STT_VOCABULARY")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            stt_queue_size: std::env::var("STT_QUEUE_SIZE")
+                .unwrap_or_else(|_| "16".to_string())
+                .parse()
+                .unwrap_or(16),
+            stt_window_secs: std::env::var("STT_WINDOW_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            stt_window_overlap_secs: std::env::var("STT_WINDOW_OVERLAP_SECS")
+                .unwrap_or_else(|_| "2".to_string())
+                .parse()
+                .unwrap_or(2),
             max_context_tokens: std::env::var("MAX_CONTEXT_TOKENS")
                 .unwrap_or_else(|_| "4000".to_string())
                 .parse()
                 .unwrap_or(4000),
+            max_agent_steps: std::env::var("MAX_AGENT_STEPS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .unwrap_or(5),
+            quota_user_daily: std::env::var("QUOTA_USER_DAILY")
+                .unwrap_or_else(|_| "100000".to_string())
+                .parse()
+                .unwrap_or(100_000),
+            quota_user_monthly: std::env::var("QUOTA_USER_MONTHLY")
+                .unwrap_or_else(|_| "1500000".to_string())
+                .parse()
+                .unwrap_or(1_500_000),
+            quota_admin_daily: std::env::var("QUOTA_ADMIN_DAILY")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .unwrap_or(0),
+            quota_admin_monthly: std::env::var("QUOTA_ADMIN_MONTHLY")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .unwrap_or(0),
         })
     }
 
@@ -73,6 +73,65 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // Agent loop: assistant tool calls and the tool results that answer them
+        sqlx::query("ALTER TABLE messages ADD COLUMN IF NOT EXISTS tool_calls JSONB")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("ALTER TABLE messages ADD COLUMN IF NOT EXISTS tool_call_id TEXT")
+            .execute(&self.pool)
+            .await?;
+
+        // Which model produced an assistant message (differs from the default on fallback)
+        sqlx::query("ALTER TABLE messages ADD COLUMN IF NOT EXISTS model TEXT")
+            .execute(&self.pool)
+            .await?;
+
+        // Real token usage reported by the API for the call that produced a message
+        sqlx::query("ALTER TABLE messages ADD COLUMN IF NOT EXISTS prompt_tokens INT")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("ALTER TABLE messages ADD COLUMN IF NOT EXISTS completion_tokens INT")
+            .execute(&self.pool)
+            .await?;
+
+        // Telegram file_id of a photo attached to a user message (re-downloaded for follow-ups)
+        sqlx::query("ALTER TABLE messages ADD COLUMN IF NOT EXISTS image_file_id TEXT")
+            .execute(&self.pool)
+            .await?;
+
+        // Every LLM call's token usage, for per-user quotas
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS usage_ledger (
+                id BIGSERIAL PRIMARY KEY,
+                user_id BIGINT NOT NULL REFERENCES users(id),
+                conversation_id UUID REFERENCES conversations(id) ON DELETE SET NULL,
+                kind TEXT NOT NULL,
+                model TEXT NOT NULL,
+                prompt_tokens INT NOT NULL DEFAULT 0,
+                completion_tokens INT NOT NULL DEFAULT 0,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            )"#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Bot-wide settings changed at runtime by admins (e.g. the global /vocab)
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS bot_settings (
+                key TEXT PRIMARY KEY,
+                value JSONB NOT NULL,
+                updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            )"#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_usage_ledger_user ON usage_ledger(user_id, created_at)")
+            .execute(&self.pool)
+            .await?;
+
         sqlx::query("CREATE INDEX IF NOT EXISTS idx_messages_conv ON messages(conversation_id, created_at)")
             .execute(&self.pool)
             .await?;
@@ -145,6 +204,32 @@ impl Database {
         Ok(())
     }
 
+    // ── Bot Settings ───────────────────────────────────────────────
+
+    pub async fn get_bot_setting(&self, key: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        let row: Option<(serde_json::Value,)> =
+            sqlx::query_as("SELECT value FROM bot_settings WHERE key = $1")
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(row.map(|r| r.0))
+    }
+
+    pub async fn set_bot_setting(&self, key: &str, value: &serde_json::Value) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO bot_settings (key, value)
+            VALUES ($1, $2)
+            ON CONFLICT (key) DO UPDATE SET value = $2, updated_at = NOW()
+            "#,
+        )
+        .bind(key)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
     // ── Conversation Operations ────────────────────────────────────
 
     pub async fn create_conversation(
@@ -198,18 +283,45 @@ impl Database {
         role: &str,
         content: &str,
         token_count: i32,
+    ) -> anyhow::Result<models::Message> {
+        self.insert_message(
+            conversation_id,
+            &models::NewMessage {
+                role,
+                content,
+                token_count,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Insert a message with all optional fields (tool calls, tool call ID, ...).
+    pub async fn insert_message(
+        &self,
+        conversation_id: uuid::Uuid,
+        new: &models::NewMessage<'_>,
     ) -> anyhow::Result<models::Message> {
         let msg = sqlx::query_as::<_, models::Message>(
             r#"
-            INSERT INTO messages (conversation_id, role, content, token_count)
-            VALUES ($1, $2, $3, $4)
+            INSERT INTO messages (
+                conversation_id, role, content, token_count, tool_calls, tool_call_id, model,
+                prompt_tokens, completion_tokens, image_file_id
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
             RETURNING *
             "#,
         )
         .bind(conversation_id)
-        .bind(role)
-        .bind(content)
-        .bind(token_count)
+        .bind(new.role)
+        .bind(new.content)
+        .bind(new.token_count)
+        .bind(new.tool_calls)
+        .bind(new.tool_call_id)
+        .bind(new.model)
+        .bind(new.prompt_tokens)
+        .bind(new.completion_tokens)
+        .bind(new.image_file_id)
         .fetch_one(&self.pool)
         .await?;
 
@@ -235,17 +347,21 @@ impl Database {
         Ok(msgs)
     }
 
-    pub async fn get_total_tokens(
+    /// Sum of API-reported prompt and completion tokens for a conversation.
+    pub async fn get_conversation_usage(
         &self,
         conversation_id: uuid::Uuid,
-    ) -> anyhow::Result<i64> {
-        let row: (i64,) = sqlx::query_as(
-            "SELECT COALESCE(SUM(token_count), 0) FROM messages WHERE conversation_id = $1",
+    ) -> anyhow::Result<(i64, i64)> {
+        let row: (i64, i64) = sqlx::query_as(
+            r#"
+            SELECT COALESCE(SUM(prompt_tokens), 0), COALESCE(SUM(completion_tokens), 0)
+            FROM messages WHERE conversation_id = $1
+            "#,
         )
         .bind(conversation_id)
         .fetch_one(&self.pool)
         .await?;
-        Ok(row.0)
+        Ok(row)
     }
 
     pub async fn delete_oldest_messages(
@@ -275,6 +391,53 @@ impl Database {
         Ok(result.rows_affected() as i64)
     }
 
+    // ── Usage Ledger Operations ────────────────────────────────────
+
+    pub async fn record_usage(
+        &self,
+        user_id: i64,
+        conversation_id: Option<uuid::Uuid>,
+        kind: &str,
+        model: &str,
+        prompt_tokens: i32,
+        completion_tokens: i32,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO usage_ledger (user_id, conversation_id, kind, model, prompt_tokens, completion_tokens)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(user_id)
+        .bind(conversation_id)
+        .bind(kind)
+        .bind(model)
+        .bind(prompt_tokens)
+        .bind(completion_tokens)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Total tokens a user consumed (today, this calendar month), in UTC.
+    pub async fn get_user_usage(&self, user_id: i64) -> anyhow::Result<(i64, i64)> {
+        let row: (i64, i64) = sqlx::query_as(
+            r#"
+            SELECT
+                COALESCE(SUM(prompt_tokens + completion_tokens)
+                    FILTER (WHERE created_at >= date_trunc('day', NOW() AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'), 0),
+                COALESCE(SUM(prompt_tokens + completion_tokens), 0)
+            FROM usage_ledger
+            WHERE user_id = $1
+              AND created_at >= date_trunc('month', NOW() AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
     // ── Approval Operations ────────────────────────────────────────
 
     pub async fn create_approval(
@@ -30,6 +30,32 @@ pub struct Message {
     pub content: String,
     pub token_count: i32,
     pub created_at: DateTime<Utc>,
+    /// Tool calls requested by an assistant message (JSON array of `ToolCall`).
+    pub tool_calls: Option<serde_json::Value>,
+    /// For `tool` messages: the ID of the call this message answers.
+    pub tool_call_id: Option<String>,
+    /// For assistant messages: the model that generated it.
+    pub model: Option<String>,
+    /// For assistant messages: API-reported prompt tokens of the call.
+    pub prompt_tokens: Option<i32>,
+    /// For assistant messages: API-reported completion tokens of the call.
+    pub completion_tokens: Option<i32>,
+    /// For user messages: Telegram file_id of an attached photo.
+    pub image_file_id: Option<String>,
+}
+
+/// Fields for inserting a new message. Optional fields default to NULL.
+#[derive(Debug, Default)]
+pub struct NewMessage<'a> {
+    pub role: &'a str,
+    pub content: &'a str,
+    pub token_count: i32,
+    pub tool_calls: Option<&'a serde_json::Value>,
+    pub tool_call_id: Option<&'a str>,
+    pub model: Option<&'a str>,
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: Option<i32>,
+    pub image_file_id: Option<&'a str>,
 }
 
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
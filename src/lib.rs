@@ -1,5 +1,6 @@
 pub mod agent;
 pub mod ai;
+pub mod audio;
 pub mod bot;
 pub mod config;
 pub mod db;
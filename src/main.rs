@@ -2,10 +2,10 @@ use std::sync::Arc;
 
 use teloxide::prelude::*;
 use anyhow::Context;
-use tracing_subscriber::EnvFilter;
 
 mod agent;
 mod ai;
+mod audio;
 mod bot;
 mod config;
 mod db;
@@ -38,7 +38,12 @@ async fn run() -> anyhow::Result<()> {
 
     // ── 1. Load Config ─────────────────────────────────────────────
     let config = AppConfig::from_env().context("Failed to load config")?;
-    tracing::info!("Config loaded. Model: {}", config.groq_model);
+    tracing::info!(
+        "Config loaded. Provider: {} ({}), model: {}",
+        config.llm_provider,
+        config.llm_base_url,
+        config.groq_model
+    );
 
     // ── 2. Initialize Database ─────────────────────────────────────
     let db = Database::connect(&config.database_url).await.context("Failed to connect to database")?;
@@ -47,25 +52,71 @@ async fn run() -> anyhow::Result<()> {
 
     // ── 3. Initialize AI Engines ───────────────────────────────────
     
-    // STT (Whisper)
-    let stt = ai::stt::SttEngine::new(&config.whisper_model_path).context("Failed to initialize STT engine")?;
+    // STT (Whisper, on a dedicated worker pool)
+    let decoding = ai::stt::DecodingOptions {
+        sampling: ai::stt::Sampling::from_name(
+            &config.stt_sampling,
+            config.stt_beam_size,
+            config.stt_best_of,
+        ),
+        threads: config.stt_threads,
+        temperature: config.stt_temperature,
+        temperature_inc: config.stt_temperature_inc,
+    };
+    tracing::info!("Whisper decoding: {:?}", decoding);
+    let stt_models = Arc::new(
+        ai::whisper_models::ModelRegistry::new(&config)
+            .context("Failed to initialize STT engine")?,
+    );
+    let stt_engine = ai::stt::SttEngine::new(stt_models.clone(), decoding);
+    let remote = ai::stt_backend::RemoteSttBackend::from_config(&config)
+        .context("Failed to initialize STT backend")?;
+    let stt_backend: Arc<dyn ai::stt_backend::SttBackend> = match remote {
+        // Local whisper stays loaded lazily as the fallback
+        Some(remote) => Arc::new(ai::stt_backend::FallbackSttBackend::new(
+            Box::new(remote),
+            Box::new(stt_engine),
+        )),
+        None => {
+            // Load the default model now so the first voice message isn't slow
+            let default_tier = ai::whisper_models::ModelTier::from_str_loose(&config.stt_model_tier)
+                .unwrap_or(ai::whisper_models::ModelTier::Base);
+            if let Some(key) = stt_models.resolve(default_tier, &config.default_stt_language) {
+                stt_models.get(key).context("Failed to load the default whisper model")?;
+            }
+            Arc::new(stt_engine)
+        }
+    };
+    let stt = ai::stt_pool::TranscriptionPool::new(stt_backend, config.stt_workers, config.stt_queue_size)
+        .context("Failed to start transcription workers")?;
     tracing::info!("✅ STT engine initialized.");
     
-    // TTS (Piper + XTTS)
-    let tts = ai::tts::TtsManager::new(&config);
-    tracing::info!("✅ TTS engine initialized.");
-
-    // LLM (Groq)
+    // TTS (engines from TTS_ENGINES)
+    let tts = ai::tts::TtsRegistry::from_config(&config).context("Failed to set up TTS engines")?;
+    tracing::info!(
+        "✅ TTS engines initialized ({}).",
+        tts.backends().iter().map(|b| b.id()).collect::<Vec<_>>().join(", ")
+    );
+
+    // LLM (Groq or any OpenAI-compatible provider)
     let llm = ai::llm::LlmClient::new(&config);
-    tracing::info!("✅ LLM client initialized.");
+    tracing::info!("✅ LLM client initialized ({}).", llm.provider_name());
 
     // ── 4. Start Bot ───────────────────────────────────────────────
     
+    // Admins' /vocab global edits outlive restarts; STT_VOCABULARY only seeds the list
+    let global_vocabulary = match db.get_bot_setting(bot::GLOBAL_VOCABULARY_KEY).await? {
+        Some(saved) => serde_json::from_value(saved).context("Invalid saved global vocabulary")?,
+        None => config.stt_vocabulary.clone(),
+    };
+
     let state = Arc::new(bot::AppState {
         model_override: tokio::sync::RwLock::new(config.groq_model.clone()),
+        global_vocabulary: tokio::sync::RwLock::new(global_vocabulary),
         config: config.clone(),
         db,
         stt,
+        stt_models,
         tts,
         llm,
     });